@@ -6,13 +6,8 @@ use rayon::prelude::*;
 use rand::thread_rng;
 
 // Structs
-use coalescence::Coalescent;
+use coalescence::{Coalescent, InfiniteSites};
 use average::Variance;
-use rand_distr::Poisson;
-
-
-// Traits
-use rand::distributions::Distribution;
 
 
 
@@ -131,12 +126,14 @@ fn heterozygosity_sample(group_sizes: &Vec<usize>, samples: usize) -> Vec<Varian
             .map(|_| {
                 let coalescent = Coalescent::new(*group_size, thread_rng());
                 let mut rng = thread_rng();
-                let depth = coalescent.sample_genealogy(&mut rng).divergence(0, 1) / 2.0;
-                let poi = Poisson::new(depth).unwrap();
-
-                let mutations_1: u64 = poi.sample(&mut rng);
-                let mutations_2: u64 = poi.sample(&mut rng);
-                ((mutations_1 - mutations_2) % 2 == 0) as u32 as f64
+                let genealogy = coalescent.sample_genealogy(&mut rng);
+                let haplotypes = InfiniteSites::new(2.0).sample_haplotypes(&genealogy, &mut rng);
+
+                let matrix = haplotypes.matrix();
+                let differences = (0..haplotypes.num_segregating_sites())
+                    .filter(|&site| matrix[0][site] != matrix[1][site])
+                    .count();
+                (differences % 2 == 0) as u32 as f64
             })
             .collect::<Vec<f64>>()
             .iter()
@@ -0,0 +1,402 @@
+//! Coalescent with recombination.
+//!
+//! Along a sequence, recombination makes different positions have different
+//! genealogies. Going backwards in time, each lineage carries ancestral
+//! material, the parts of the sequence inherited by the group, and splits in
+//! two lineages at rate proportional to the span of its ancestral material:
+//! the material to the left of the breakpoint follows one parent and the rest
+//! follows the other. As in the standard coalescent, every pair of lineages
+//! coalesces at rate one.
+//!
+//! The result is an ancestral recombination graph (ARG), simulated with
+//! Hudson's algorithm: lineages are followed until every position of the
+//! sequence reaches its most recent common ancestor. From the ARG, the
+//! marginal genealogy of every position can be extracted.
+//!
+
+// Types
+use rand_distr::Exp;
+use rand::distributions::WeightedIndex;
+use crate::Genealogy;
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Event of an ancestral recombination graph. Nodes are numbered in order of
+/// appearance, the first ones being the individuals of the group.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgEvent {
+    /// Two nodes find a common ancestor, the parent node.
+    Coalescence {
+        /// Time of the event, measured backwards from the present.
+        time: f64,
+        /// Nodes that coalesce.
+        children: [usize; 2],
+        /// Common ancestor node.
+        parent: usize,
+    },
+    /// A node inherits its material from two parent nodes: the sequence to the
+    /// left of the breakpoint from the first parent and the rest from the second.
+    Recombination {
+        /// Time of the event, measured backwards from the present.
+        time: f64,
+        /// Node that recombines.
+        child: usize,
+        /// Parent nodes, left and right of the breakpoint.
+        parents: [usize; 2],
+        /// Position of the breakpoint in the sequence.
+        breakpoint: f64,
+    },
+}
+
+/// Part of the ancestral material of a lineage, where all positions are
+/// ancestral to the same individuals.
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    left: f64,
+    right: f64,
+    representative: usize, // one of the individuals it is ancestral to
+    count: usize, // number of individuals it is ancestral to
+}
+
+/// Coalescence of two lineages, restricted to an interval of the sequence.
+#[derive(Debug, Clone, PartialEq)]
+struct MarginalMerger {
+    time: f64,
+    left: f64,
+    right: f64,
+    representatives: [usize; 2],
+}
+
+#[derive(Debug, Clone)]
+struct Lineage {
+    node: usize,
+    segments: Vec<Segment>, // sorted and disjoint
+}
+
+impl Lineage {
+    /// Length from the first to the last position of ancestral material.
+    fn span(&self) -> f64 {
+        self.segments.last().unwrap().right - self.segments[0].left
+    }
+}
+
+/// Ancestral recombination graph of a group of individuals along a sequence.
+///
+/// This struct is created by the ``sample_arg`` method on RecombiningCoalescent.
+/// See its documentation for more.
+#[derive(Debug, Clone)]
+pub struct AncestralRecombinationGraph {
+    group_size: usize,
+    sequence_length: f64,
+    events: Vec<ArgEvent>, // sorted by time
+    breakpoints: Vec<f64>, // sorted
+    marginal_mergers: Vec<MarginalMerger>, // sorted by time
+}
+
+impl AncestralRecombinationGraph {
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Length of the sequence.
+    pub fn sequence_length(&self) -> f64 {
+        self.sequence_length
+    }
+
+    /// Events of the graph, sorted by time.
+    pub fn events(&self) -> &[ArgEvent] {
+        &self.events
+    }
+
+    /// Positions of all recombination breakpoints, sorted. Marginal genealogies
+    /// can only change at these positions.
+    pub fn breakpoints(&self) -> &[f64] {
+        &self.breakpoints
+    }
+
+    /// Genealogy of the group at position ``position`` of the sequence.
+    ///
+    /// # Panics
+    ///
+    /// If ``position`` is not in the sequence.
+    pub fn marginal_genealogy(&self, position: f64) -> Genealogy {
+        assert!(0.0 <= position && position < self.sequence_length, "The position is not in the sequence.");
+
+        let timed_steps = self.marginal_mergers
+            .iter()
+            .filter(|merger| merger.left <= position && position < merger.right)
+            .map(|merger| (merger.time, vec![merger.representatives.to_vec()]))
+            .collect();
+
+        Genealogy::from_timed_steps(self.group_size, timed_steps)
+    }
+
+    /// Genealogies of the group along the sequence, together with the interval
+    /// ``(left, right)`` where each of them is the marginal genealogy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let coalescent = coalescence::RecombiningCoalescent::new(10, 1.0, 5.0);
+    /// let mut rng = rand::thread_rng();
+    /// let arg = coalescent.sample_arg(&mut rng);
+    ///
+    /// let mean_depth: f64 = arg.marginal_genealogies()
+    ///     .iter()
+    ///     .map(|((left, right), genealogy)| (right - left) * genealogy.depth())
+    ///     .sum();
+    /// ```
+    pub fn marginal_genealogies(&self) -> Vec<((f64, f64), Genealogy)> {
+        let mut bounds = vec![0.0];
+        bounds.extend(self.breakpoints.iter().cloned());
+        bounds.push(self.sequence_length);
+        bounds.dedup();
+
+        bounds
+            .windows(2)
+            .map(|interval| ((interval[0], interval[1]), self.marginal_genealogy(interval[0])))
+            .collect()
+    }
+}
+
+/// Coalescent with recombination of a group along a sequence.
+///
+/// Time is measured in coalescent units, and each lineage recombines at rate
+/// ``recombination_rate`` per unit of sequence length in the span of its
+/// ancestral material.
+///
+/// # Examples
+///
+/// ```
+/// let group_size = 10;
+/// let coalescent = coalescence::RecombiningCoalescent::new(group_size, 1.0, 2.0);
+///
+/// let mut rng = rand::thread_rng();
+/// let arg = coalescent.sample_arg(&mut rng);
+/// let genealogy = arg.marginal_genealogy(0.5);
+///
+/// assert_eq!(genealogy.group_size(), group_size);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecombiningCoalescent {
+    group_size: usize,
+    sequence_length: f64,
+    recombination_rate: f64,
+}
+
+impl RecombiningCoalescent {
+    /// Creates a new RecombiningCoalescent.
+    ///
+    /// # Panics
+    ///
+    /// If ``sequence_length`` is not positive or ``recombination_rate`` is negative.
+    pub fn new(group_size: usize, sequence_length: f64, recombination_rate: f64) -> Self {
+        assert!(sequence_length > 0.0, "The length of the sequence must be positive.");
+        assert!(recombination_rate >= 0.0, "The recombination rate must be non-negative.");
+
+        RecombiningCoalescent { group_size, sequence_length, recombination_rate }
+    }
+
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Length of the sequence.
+    pub fn sequence_length(&self) -> f64 {
+        self.sequence_length
+    }
+
+    /// Rate of recombination per lineage and per unit of sequence length.
+    pub fn recombination_rate(&self) -> f64 {
+        self.recombination_rate
+    }
+
+    /// Sample an ancestral recombination graph, until every position of the
+    /// sequence reaches its most recent common ancestor.
+    ///
+    /// # Remarks
+    ///
+    /// Lineages with no ancestral material are not followed, and neither is
+    /// material that already reached its most recent common ancestor.
+    pub fn sample_arg<S>(&self, rng: &mut S) -> AncestralRecombinationGraph
+    where
+        S: Rng,
+    {
+        // Initialize lineages
+
+        let mut lineages: Vec<Lineage> = Vec::with_capacity(self.group_size);
+        if self.group_size > 1 {
+            lineages.extend((0..self.group_size).map(|individual| Lineage {
+                node: individual,
+                segments: vec![Segment { left: 0.0, right: self.sequence_length, representative: individual, count: 1 }],
+            }));
+        }
+        let mut num_nodes = self.group_size;
+        let mut time = 0.0;
+        let mut events = Vec::new();
+        let mut breakpoints = Vec::new();
+        let mut marginal_mergers = Vec::new();
+
+        // Generate events
+
+        while !lineages.is_empty() {
+            let k = lineages.len();
+            let coalescence_rate = (k * (k - 1) / 2) as f64;
+            let spans: Vec<f64> = lineages.iter().map(|lineage| lineage.span()).collect();
+            let recombination_rate = self.recombination_rate * spans.iter().sum::<f64>();
+            let total_rate = coalescence_rate + recombination_rate;
+
+            time += Exp::new(total_rate).unwrap().sample(rng);
+
+            if rng.gen::<f64>() * total_rate < coalescence_rate {
+                // Coalescence
+
+                let pair = rand::seq::index::sample(rng, k, 2);
+                let (first, second) = (pair.index(0).min(pair.index(1)), pair.index(0).max(pair.index(1)));
+                let lineage_2 = lineages.swap_remove(second);
+                let lineage_1 = lineages.swap_remove(first);
+
+                let segments = self.merge_segments(&lineage_1.segments, &lineage_2.segments, time, &mut marginal_mergers);
+                events.push(ArgEvent::Coalescence { time, children: [lineage_1.node, lineage_2.node], parent: num_nodes });
+                if !segments.is_empty() {
+                    lineages.push(Lineage { node: num_nodes, segments });
+                }
+                num_nodes += 1;
+            } else {
+                // Recombination
+
+                let index = WeightedIndex::new(&spans).unwrap().sample(rng);
+                let lineage = lineages.swap_remove(index);
+                let (start, end) = (lineage.segments[0].left, lineage.segments.last().unwrap().right);
+                let breakpoint = start + rng.gen::<f64>() * (end - start);
+
+                let mut left_segments = Vec::new();
+                let mut right_segments = Vec::new();
+                for segment in lineage.segments {
+                    if segment.right <= breakpoint {
+                        left_segments.push(segment);
+                    } else if segment.left >= breakpoint {
+                        right_segments.push(segment);
+                    } else {
+                        left_segments.push(Segment { right: breakpoint, ..segment.clone() });
+                        right_segments.push(Segment { left: breakpoint, ..segment });
+                    }
+                }
+
+                events.push(ArgEvent::Recombination {
+                    time,
+                    child: lineage.node,
+                    parents: [num_nodes, num_nodes + 1],
+                    breakpoint,
+                });
+                breakpoints.push(breakpoint);
+                for (node, segments) in [(num_nodes, left_segments), (num_nodes + 1, right_segments)] {
+                    if !segments.is_empty() {
+                        lineages.push(Lineage { node, segments });
+                    }
+                }
+                num_nodes += 2;
+            }
+        }
+
+        // Finish
+
+        breakpoints.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        AncestralRecombinationGraph {
+            group_size: self.group_size,
+            sequence_length: self.sequence_length,
+            events,
+            breakpoints,
+            marginal_mergers,
+        }
+    }
+
+    /// Ancestral material of the common ancestor of two lineages. Where both
+    /// lineages have material, a marginal merger is recorded, and material that
+    /// reaches its most recent common ancestor is dropped.
+    fn merge_segments(&self, segments_1: &[Segment], segments_2: &[Segment], time: f64, marginal_mergers: &mut Vec<MarginalMerger>) -> Vec<Segment> {
+        let mut bounds: Vec<f64> = segments_1
+            .iter()
+            .chain(segments_2)
+            .flat_map(|segment| vec![segment.left, segment.right])
+            .collect();
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        bounds.dedup();
+
+        let covering = |segments: &[Segment], left: f64, right: f64| {
+            segments
+                .iter()
+                .find(|segment| segment.left <= left && right <= segment.right)
+                .cloned()
+        };
+
+        let mut merged: Vec<Segment> = Vec::new();
+        for interval in bounds.windows(2) {
+            let (left, right) = (interval[0], interval[1]);
+            let segment = match (covering(segments_1, left, right), covering(segments_2, left, right)) {
+                (Some(segment_1), Some(segment_2)) => {
+                    marginal_mergers.push(MarginalMerger {
+                        time,
+                        left,
+                        right,
+                        representatives: [segment_1.representative, segment_2.representative],
+                    });
+                    let count = segment_1.count + segment_2.count;
+                    let representative = segment_1.representative.min(segment_2.representative);
+                    match count < self.group_size {
+                        true => Segment { left, right, representative, count },
+                        false => continue,
+                    }
+                },
+                (Some(segment), None) | (None, Some(segment)) => Segment { left, right, ..segment },
+                (None, None) => continue,
+            };
+
+            // Join with the previous segment if possible
+
+            match merged.last_mut() {
+                Some(last) if last.right == segment.left
+                    && last.representative == segment.representative
+                    && last.count == segment.count => last.right = segment.right,
+                _ => merged.push(segment),
+            }
+        }
+
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn marginal_genealogies_are_trees() {
+        let group_size = 8;
+        let coalescent = RecombiningCoalescent::new(group_size, 1.0, 3.0);
+        let arg = coalescent.sample_arg(&mut rand_pcg::Pcg32::seed_from_u64(4));
+
+        assert!(!arg.breakpoints().is_empty());
+        for ((left, right), genealogy) in arg.marginal_genealogies() {
+            assert!(left < right);
+            assert_eq!(genealogy.steps().len(), group_size - 1);
+            assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+            assert_eq!(genealogy.depth(), arg.marginal_genealogy((left + right) / 2.0).depth());
+        }
+    }
+
+    #[test]
+    fn no_recombination() {
+        let coalescent = RecombiningCoalescent::new(5, 1.0, 0.0);
+        let arg = coalescent.sample_arg(&mut rand_pcg::Pcg32::seed_from_u64(5));
+
+        assert!(arg.breakpoints().is_empty());
+        assert_eq!(arg.events().len(), 4);
+        assert_eq!(arg.marginal_genealogies().len(), 1);
+    }
+}
@@ -9,11 +9,20 @@
 //! distributes as an exponential with mean 2 / (n (n - 1)), the inverse
 //! of the number of possible partitions.
 //! 
+//! If the population size changes over time, the process is the same up to
+//! a rescaling of time given by its ``Demography``. Bottlenecks of the 
+//! demography allow several sets to be joint at the same time. 
+//! 
+//! The ``Ploidy`` of the population also rescales time and, for diploids 
+//! with selfing, lets the copies carried by an individual merge at once when 
+//! it is sampled. 
+//! 
 
 // Types
 use partitions::PartitionVec;
-use rand_distr::Exp;
-use crate::Genealogy;
+use rand_distr::{Exp, Exp1};
+use crate::{Demography, Genealogy, Ploidy, Step};
+use crate::genealogy::apply_step;
 
 // Traits
 use markovian::traits::CMarkovChainTrait;
@@ -42,7 +51,12 @@ pub struct Coalescent<R>
 where
     R: Rng + Clone + core::fmt::Debug,
 {
-    state: PartitionVec<()>, // No selection
+    state: PartitionVec<()>, // No selection, see SelectiveCoalescent
+    demography: Demography,
+    ploidy: Ploidy,
+    individuals_pending: bool, // whether copies carried by the same individual can still merge at once
+    sampling_times: Vec<f64>,
+    time: f64,
     rng: R,
 }
 
@@ -65,7 +79,115 @@ where
         let state: PartitionVec<()> =
             PartitionVec::from_iter((0..group_size).map(|_| ()));
 
-        Coalescent { state, rng }
+        let sampling_times = vec![0.0; group_size];
+
+        Coalescent {
+            state,
+            demography: Demography::default(),
+            ploidy: Ploidy::Haploid,
+            individuals_pending: false,
+            sampling_times,
+            time: 0.0,
+            rng,
+        }
+    }
+
+    /// Creates a new Coalescent of ``group_size`` copies of a locus in a 
+    /// population with ploidy ``ploidy``. Time is measured in units of N 
+    /// generations, where N is the number of individuals. 
+    /// 
+    /// # Examples
+    /// 
+    /// A group of five diploid individuals of a partially selfing species. 
+    /// ```
+    /// use coalescence::{Coalescent, Ploidy};
+    /// 
+    /// let rng = rand::thread_rng();
+    /// let coalescent = Coalescent::with_ploidy(10, Ploidy::Diploid { selfing_rate: 0.5 }, rng);
+    /// 
+    /// let mut rng = rand::thread_rng();
+    /// let genealogy = coalescent.sample_genealogy(&mut rng);
+    /// ```
+    pub fn with_ploidy(group_size: usize, ploidy: Ploidy, rng: R) -> Self {
+        let mut coalescent = Coalescent::new(group_size, rng);
+        coalescent.ploidy = ploidy;
+        coalescent.individuals_pending = ploidy.inbreeding_coefficient() > 0.0;
+        coalescent
+    }
+
+    /// Current time of the process, measured backwards from the present.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Ploidy of the population. 
+    pub fn ploidy(&self) -> Ploidy {
+        self.ploidy
+    }
+
+    /// Demographic history of the population. 
+    pub fn demography(&self) -> &Demography {
+        &self.demography
+    }
+
+    /// Change the demographic history of the population. 
+    /// 
+    /// # Examples
+    /// 
+    /// A population that went through a period of small size. 
+    /// ```
+    /// let mut demography = coalescence::Demography::default();
+    /// demography.add_epoch(0.1, 0.01).add_epoch(0.2, 1.0);
+    ///
+    /// let group_size = 100;
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = coalescence::Coalescent::new(group_size, rng);
+    /// coalescent.set_demography(demography);
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// let genealogy = coalescent.sample_genealogy(&mut rng);
+    /// ```
+    pub fn set_demography(&mut self, demography: Demography) -> &mut Self {
+        self.demography = demography;
+        self
+    }
+
+    /// Time, measured backwards from the present, at which each individual 
+    /// was sampled. 
+    pub fn sampling_times(&self) -> &[f64] {
+        &self.sampling_times
+    }
+
+    /// Change the time, measured backwards from the present, at which each 
+    /// individual was sampled. The lineage of an individual can not merge 
+    /// before its sampling time. 
+    /// 
+    /// # Panics
+    /// 
+    /// If there is not one sampling time per individual or some of them 
+    /// is negative. 
+    /// 
+    /// # Examples
+    /// 
+    /// Ancient samples, taken at different times in the past. 
+    /// ```
+    /// let group_size = 4;
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = coalescence::Coalescent::new(group_size, rng);
+    /// coalescent.set_sampling_times(vec![0.0, 0.0, 0.5, 1.5]);
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// let genealogy = coalescent.sample_genealogy(&mut rng);
+    ///
+    /// assert!(genealogy.depth() > 1.5);
+    /// // Both ancient samples shorten the distance through their common ancestor.
+    /// assert!(genealogy.divergence(2, 3) <= 2.0 * genealogy.depth() - 2.0 + 1e-10);
+    /// ```
+    pub fn set_sampling_times(&mut self, sampling_times: Vec<f64>) -> &mut Self {
+        assert_eq!(sampling_times.len(), self.state.len(), "There must be one sampling time per individual.");
+        assert!(sampling_times.iter().all(|&time| time >= 0.0), "Sampling times must be non-negative.");
+        self.sampling_times = sampling_times;
+        self
     }
 
     /// Mutable reference to the internal random number generator. 
@@ -132,6 +254,9 @@ where
     /// No internal state changes, including the internal
     /// random number generator. This is why this methods requires a rng.  
     ///
+    /// If the sets never merge into one, because the demography makes
+    /// the waiting time infinite, the path stops at the last state reached. 
+    ///
     /// # Examples
     ///
     /// 
@@ -152,18 +277,12 @@ where
     {
         // Initialize a Coalescent
 
-        let initial_group_size: usize = self.state().len();
-        let other_rng = rng.clone();
-        let mut coalescent_process = Coalescent::new(initial_group_size, other_rng);
+        let mut coalescent_process = self.restart(rng.clone());
 
         // Generate a realizations
 
         let mut realizations = vec![(0.0, coalescent_process.state().clone())];
-        loop {
-            let (time_step, state) = match coalescent_process.next() {
-                Some((t, s)) => (t, s),
-                None => break,
-            };
+        for (time_step, state) in coalescent_process.by_ref() {
             realizations.push((time_step, state));
         }
 
@@ -176,10 +295,26 @@ where
         realizations
     }
 
-    /// Peeks a possible next pair of indices to be joint, chosen 
+    /// Peeks a possible next step, i.e. sets of indices to be joint, chosen 
     /// according to the stochastic process. This does not change the 
     /// state of the ``Coalescent``. 
     /// 
+    /// Outside bottlenecks, the step is a single pair of indices. 
+    /// At a bottleneck, the step can have any number of mergers, 
+    /// possibly none. When serially sampled individuals enter the 
+    /// process, the step only merges copies carried by the same individual, 
+    /// if any. 
+    /// 
+    /// Returns ``None`` when only one set is left, or when the remaining
+    /// sets never merge because the demography makes the waiting time
+    /// infinite and no more individuals are sampled. 
+    /// 
+    /// # Remarks
+    /// 
+    /// Since version 0.2.0, the step is a ``Step``, a list of sets of 
+    /// indices, instead of a single pair ``[usize; 2]``. The pair of 
+    /// earlier versions is ``step[0]`` outside bottlenecks. 
+    /// 
     /// # Examples
     /// 
     /// ```
@@ -189,54 +324,115 @@ where
     /// let mut coalescent = coalescence::Coalescent::new(group_size, rng);
     ///
     /// // The next state will be a partition with (group_size - 1) sets. 
-    /// let (_time, index_pair) = coalescent.peek_next_step().expect("The simulation process failed!");
+    /// let (_time, step) = coalescent.peek_next_step().expect("The simulation process failed!");
     /// let current_state = coalescent.state();
     /// 
     /// assert_eq!(2, current_state.amount_of_sets());
-    /// assert!(!current_state.same_set(index_pair[0], index_pair[1]));
+    /// assert!(!current_state.same_set(step[0][0], step[0][1]));
     /// ``` 
-    pub fn peek_next_step(&mut self) -> Option<(f64, [usize; 2])> {
+    pub fn peek_next_step(&mut self) -> Option<(f64, Step)> {
         let current_partition_size = self.state.amount_of_sets();
 
         if current_partition_size == 1 {
             None
+        } else if self.individuals_pending {
+            // Copies carried by the same individual
+
+            let step = self.individuals_step(self.time);
+            Some((0.0, step))
         } else {
+            // Lineages already sampled
+
+            let representatives: Vec<usize> = self.state
+                .all_sets()
+                .filter(|set| set.clone().any(|(i, _)| self.sampling_times[i] <= self.time))
+                .map(|mut set| set.next().unwrap().0)
+                .collect();
+            let active_size = representatives.len();
+            let next_sampling_time = self.sampling_times
+                .iter()
+                .cloned()
+                .filter(|&sampling_time| sampling_time > self.time)
+                .fold(f64::INFINITY, f64::min);
+
             // Simulate time step
 
-            let rate = (current_partition_size * (current_partition_size - 1) / 2) as f64;
-            let exp = Exp::new(rate).unwrap();
-            let time_step = exp.sample(&mut self.rng());
+            let time_step = if active_size > 1 {
+                let rate = (active_size * (active_size - 1) / 2) as f64 * self.ploidy.coalescence_rate();
+                let hazard: f64 = Exp1.sample(&mut self.rng());
+                self.demography.waiting_time(self.time, hazard / rate)
+            } else {
+                f64::INFINITY
+            };
+
+            // Check for bottlenecks before the step
+
+            if let Some(bottleneck) = self.demography.next_bottleneck(self.time) {
+                if bottleneck.time() <= (self.time + time_step).min(next_sampling_time) {
+                    let time_step = bottleneck.time() - self.time;
+                    let intensity = bottleneck.intensity() * self.ploidy.coalescence_rate();
+                    let step = bottleneck_step(&representatives, intensity, &mut self.rng);
+                    return Some((time_step, step));
+                }
+            }
+
+            // Check whether sets can still merge
+
+            if time_step.is_infinite() && next_sampling_time.is_infinite() {
+                return None;
+            }
+
+            // Check for samples before the step
+
+            if next_sampling_time <= self.time + time_step {
+                let step = if self.ploidy.inbreeding_coefficient() > 0.0 {
+                    self.individuals_step(next_sampling_time)
+                } else {
+                    Vec::new()
+                };
+                return Some((next_sampling_time - self.time, step));
+            }
 
             // Choose between possible transitions
 
             let mut set_indexes = [0; 2];
-            (0..current_partition_size).choose_multiple_fill(&mut self.rng(), &mut set_indexes);
+            (0..active_size).choose_multiple_fill(&mut self.rng(), &mut set_indexes);
 
             // Get values from these sets
-            let value_indexes: Vec<usize> = (0..2)
-                .map(|i| {
-                    self.state
-                        .all_sets()
-                        .nth(set_indexes[i])
-                        .unwrap() // set
-                        .nth(0)
-                        .unwrap() // (value_index, value)
-                        .0
-                })
+            let value_indexes: Vec<usize> = set_indexes
+                .iter()
+                .map(|&i| representatives[i])
                 .collect();
-            let value_indexes = [value_indexes[0], value_indexes[1]];
 
             // Return
 
-            Some((time_step, value_indexes))
+            Some((time_step, vec![value_indexes]))
         }
     }
 
+    /// Mergers of the copies carried by each individual sampled at time
+    /// ``time``, each happening with probability the inbreeding coefficient.
+    fn individuals_step(&mut self, time: f64) -> Step {
+        let inbreeding_coefficient = self.ploidy.inbreeding_coefficient();
+        let (state, sampling_times, rng) = (&self.state, &self.sampling_times, &mut self.rng);
+        (0..state.len() / 2)
+            .map(|individual| vec![2 * individual, 2 * individual + 1])
+            .filter(|copies| sampling_times[copies[0]].max(sampling_times[copies[1]]) == time)
+            .filter(|copies| !state.same_set(copies[0], copies[1]))
+            .filter(|_| rng.gen_bool(inbreeding_coefficient))
+            .collect()
+    }
+
     /// Changes to a next state of the ``Coalescent``, chosen 
     /// according to the stochastic process and returning the indexes of 
     /// elements that represent the sets of the partitions that were joint
     /// to produce this next state. 
     /// 
+    /// # Remarks
+    /// 
+    /// Since version 0.2.0, the step is a ``Step`` instead of a single 
+    /// pair ``[usize; 2]``, see ``peek_next_step``. 
+    /// 
     /// # Examples
     /// 
     /// ```
@@ -245,20 +441,29 @@ where
     /// let mut coalescent = coalescence::Coalescent::new(group_size, rng);
     ///
     /// // The next state will be a partition with (group_size - 1) sets. 
-    /// let (_time, value_indexes) = coalescent.peek_next_step().expect("The simulation process failed!");
+    /// let (_time, step) = coalescent.next_step().expect("The simulation process failed!");
+    /// let value_indexes = &step[0];
     /// assert!(value_indexes[0] != value_indexes[1]); 
     /// assert!(value_indexes[0] < group_size && value_indexes[1] < group_size ); 
     /// ``` 
-    pub fn next_step(&mut self) -> Option<(f64, [usize; 2])> {
+    pub fn next_step(&mut self) -> Option<(f64, Step)> {
         match self.peek_next_step() {
-            Some((time_step, value_indexes)) => {
-                self.state.union(value_indexes[0], value_indexes[1]);
-                Some((time_step, value_indexes))
+            Some((time_step, step)) => {
+                self.take_step(time_step, &step);
+                Some((time_step, step))
             },
             None => None,
         }
     }
 
+    /// Changes to the state after ``step``, which happens ``time_step`` 
+    /// after the current time. Usually, the step comes from ``peek_next_step``. 
+    pub(crate) fn take_step(&mut self, time_step: f64, step: &Step) {
+        apply_step(&mut self.state, step);
+        self.individuals_pending = false;
+        self.time += time_step;
+    }
+
     /// Sample a genealogy: from the current state until there is only one set
     /// in the partition. Returns a ``Genealogy`` where postprocess is possible. 
     ///
@@ -267,6 +472,11 @@ where
     /// No internal state changes, including the internal
     /// random number generator. This is why this methods requires a rng.  
     ///
+    /// # Panics
+    ///
+    /// If the sets never merge into one, because the demography makes
+    /// the waiting time infinite. 
+    ///
     /// # Examples
     ///
     /// 
@@ -279,6 +489,22 @@ where
     /// let genealogy = coalescent.sample_genealogy(&mut rng);
     /// ```
     ///
+    /// A strong bottleneck can merge several sets at once. 
+    /// ```
+    /// let mut demography = coalescence::Demography::default();
+    /// demography.add_bottleneck(0.01, 2.0);
+    ///
+    /// let group_size = 100;
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = coalescence::Coalescent::new(group_size, rng);
+    /// coalescent.set_demography(demography);
+    ///
+    /// let mut rng = rand::thread_rng();
+    /// let genealogy = coalescent.sample_genealogy(&mut rng);
+    ///
+    /// assert!(genealogy.steps().len() < group_size - 1);
+    /// ```
+    ///
     pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
     where
         S: Rng + Clone + Debug,
@@ -286,30 +512,32 @@ where
        // Initialize a Coalescent
 
         let group_size: usize = self.state().len();
-        let other_rng = rng.clone();
-        let mut coalescent_process = Coalescent::new(group_size, other_rng);
+        let mut coalescent_process = self.restart(rng.clone());
 
         // Generate a transitions
 
         let mut path: Vec<PartitionVec<()>> = Vec::with_capacity(group_size - 1);
-        let mut steps: Vec<[usize; 2]> = Vec::with_capacity(group_size - 1);
+        let mut steps: Vec<Step> = Vec::with_capacity(group_size - 1);
         let mut time_steps: Vec<f64> = Vec::with_capacity(group_size - 1);
 
         let mut state: PartitionVec<()> =
             PartitionVec::from_iter((0..group_size).map(|_| ()));
 
         path.push(state.clone());
-        loop {
-            let (time_step, value_indexes) = match coalescent_process.next_step() {
-                Some(step) => step,
-                None => break,
-            };
-            state.union(value_indexes[0], value_indexes[1]);
+        let mut carried_time = 0.0; // from steps without mergers
+        while let Some((time_step, step)) = coalescent_process.next_step() {
+            carried_time += time_step;
+            if step.is_empty() {
+                continue;
+            }
+            apply_step(&mut state, &step);
 
             path.push(state.clone());
-            steps.push(value_indexes);
-            time_steps.push(time_step);
+            steps.push(step);
+            time_steps.push(carried_time);
+            carried_time = 0.0;
         }
+        assert_eq!(state.amount_of_sets(), 1, "The lineages never coalesce under this demography.");
 
         // Update rng
 
@@ -317,7 +545,20 @@ where
 
         // Finish
 
-        Genealogy::new(path, steps, time_steps)
+        Genealogy::new(path, steps, time_steps).with_sampling_times(self.sampling_times.clone())
+    }
+
+    /// New process with the same ploidy, demography and sampling times, starting at time zero from the 
+    /// partition of singletons of the same size as the current state. 
+    fn restart<S>(&self, rng: S) -> Coalescent<S>
+    where
+        S: Rng + Clone + Debug,
+    {
+        let mut coalescent_process = Coalescent::with_ploidy(self.state().len(), self.ploidy, rng);
+        coalescent_process
+            .set_demography(self.demography.clone())
+            .set_sampling_times(self.sampling_times.clone());
+        coalescent_process
     }
 }
 
@@ -360,11 +601,137 @@ where
     /// ``` 
     fn next(&mut self) -> Option<Self::Item> {
         match self.peek_next_step() {
-            Some((time_step, value_indexes)) => {
-                self.state.union(value_indexes[0], value_indexes[1]);
+            Some((time_step, step)) => {
+                self.take_step(time_step, &step);
                 Some((time_step, self.state.clone()))
             },
             None => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Mergers of a standard coalescent running for ``intensity`` units of time, 
+/// starting from the sets represented by ``representatives``. 
+fn bottleneck_step<S>(representatives: &[usize], intensity: f64, rng: &mut S) -> Step 
+where
+    S: Rng,
+{
+    let mut blocks: Vec<Vec<usize>> = representatives.iter().map(|&i| vec![i]).collect();
+    let mut time = 0.0;
+
+    while blocks.len() > 1 {
+        let rate = (blocks.len() * (blocks.len() - 1) / 2) as f64;
+        time += Exp::new(rate).unwrap().sample(rng);
+        if time > intensity {
+            break;
+        }
+        let pair = rand::seq::index::sample(rng, blocks.len(), 2);
+        let (first, second) = (pair.index(0).min(pair.index(1)), pair.index(0).max(pair.index(1)));
+        let block = blocks.swap_remove(second);
+        blocks[first].extend(block);
+    }
+
+    blocks.into_iter().filter(|block| block.len() > 1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn constant_demography_rescales_time() {
+        let group_size = 10;
+        let coalescent = Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(0));
+        let mut larger_coalescent = coalescent.clone();
+        larger_coalescent.set_demography(Demography::new(2.0));
+
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(1));
+        let larger_genealogy = larger_coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(1));
+
+        assert!((2.0 * genealogy.depth() - larger_genealogy.depth()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn bottleneck_merges_at_once() {
+        let group_size = 50;
+        let mut demography = Demography::default();
+        demography.add_bottleneck(1e-6, 100.0);
+        let mut coalescent = Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.set_demography(demography);
+
+        // Such a bottleneck merges all lineages into one with overwhelming probability.
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(2));
+        let merged: usize = genealogy.steps().iter().flatten().map(|merger| merger.len() - 1).sum();
+        assert_eq!(merged, group_size - 1);
+        assert!(genealogy.depth() <= 1e-6);
+    }
+
+    #[test]
+    fn ploidy_rescales_time() {
+        let group_size = 10;
+        let coalescent = Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(0));
+        let diploid_coalescent = Coalescent::with_ploidy(group_size, Ploidy::diploid(), rand_pcg::Pcg32::seed_from_u64(0));
+
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(1));
+        let diploid_genealogy = diploid_coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(1));
+
+        assert!((2.0 * genealogy.depth() - diploid_genealogy.depth()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn complete_selfing_merges_individuals_at_once() {
+        let coalescent = Coalescent::with_ploidy(10, Ploidy::Diploid { selfing_rate: 1.0 }, rand_pcg::Pcg32::seed_from_u64(0));
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(2));
+
+        assert_eq!(genealogy.time_steps()[0], 0.0);
+        assert_eq!(genealogy.steps()[0], (0..5).map(|i| vec![2 * i, 2 * i + 1]).collect::<Step>());
+        assert_eq!(genealogy.steps().len(), 5);
+    }
+
+    #[test]
+    fn complete_selfing_merges_individuals_when_sampled() {
+        let mut coalescent = Coalescent::with_ploidy(4, Ploidy::Diploid { selfing_rate: 1.0 }, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.set_sampling_times(vec![1.0, 1.0, 0.0, 0.0]);
+
+        for seed in 0..20 {
+            let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(seed));
+            assert_eq!(genealogy.steps()[0], vec![vec![2, 3]]);
+            assert_eq!(genealogy.time_steps()[0], 0.0);
+            assert!(genealogy.steps().contains(&vec![vec![0, 1]]));
+            assert!(genealogy.branches().iter().all(|branch| branch.length() >= 0.0));
+            assert!(genealogy.length() > 0.0);
+        }
+
+        let mut coalescent = Coalescent::with_ploidy(2, Ploidy::Diploid { selfing_rate: 1.0 }, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.set_sampling_times(vec![1.0, 1.0]);
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(0));
+        assert_eq!(genealogy.time_steps(), &[1.0][..]);
+        assert_eq!(genealogy.length(), 0.0);
+    }
+
+    #[test]
+    fn serial_samples_merge_after_sampling() {
+        let group_size = 6;
+        let sampling_times = vec![0.0, 0.0, 0.3, 0.3, 1.0, 2.0];
+        let mut coalescent = Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.set_sampling_times(sampling_times.clone());
+
+        for seed in 0..20 {
+            let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(seed));
+            assert_eq!(genealogy.sampling_times(), &sampling_times[..]);
+            assert_eq!(genealogy.steps().len(), group_size - 1);
+            for i in 0..group_size {
+                for j in (i + 1)..group_size {
+                    assert!(genealogy.divergence(i, j) >= (sampling_times[j] - sampling_times[i]).abs() - 1e-10);
+                }
+            }
+
+            let length = genealogy.length();
+            let graph: petgraph::Graph<(usize, usize), f64, petgraph::Undirected, u32> = genealogy.into();
+            assert!(graph.raw_edges().iter().all(|edge| edge.weight >= 0.0));
+            let graph_length: f64 = graph.raw_edges().iter().map(|edge| edge.weight).sum();
+            assert!((length - graph_length).abs() < 1e-10);
+        }
+    }
+}
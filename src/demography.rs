@@ -0,0 +1,664 @@
+//! Demographic history of a population.
+//!
+//! The standard coalescent assumes a population of constant size. When the
+//! size of the population changes over time, the coalescent process runs with
+//! a time-dependent rate: with k lineages and relative population size λ(t) at
+//! time t (measured backwards from the present), the rate of coalescence is
+//! k (k - 1) / (2 λ(t)).
+//!
+//! Equivalently, the coalescent of a variable population is a standard
+//! coalescent where the time is rescaled. A ``Demography`` performs this
+//! rescaling: it transforms waiting times of a constant population into
+//! waiting times of the population it describes, by inverting the cumulative
+//! rate of coalescence ∫ 1 / λ(s) ds exactly.
+//!
+//! Within each epoch, the population size is either constant, changes
+//! exponentially or follows any user-supplied function N(t). In the last
+//! case, the cumulative rate of coalescence is integrated and inverted
+//! numerically. Instantaneous size changes are given by the start of a new
+//! epoch. Bottlenecks are instantaneous events where the population is so small
+//! that an amount of coalescent time, its intensity, is compressed into an
+//! instant: several lineages can merge at the same time.
+//!
+
+// Types
+use std::sync::Arc;
+
+/// Length of the first integration step of variable epochs, relative to the
+/// population size at its start. Each step accumulates a rate of coalescence
+/// of about this amount, and later steps adapt to the size function.
+const INITIAL_INTEGRATION_STEP: f64 = 0.1;
+
+/// Relative error allowed in the rate of coalescence of each integration step.
+const INTEGRATION_TOLERANCE: f64 = 1e-10;
+
+/// Relative population size as a function of time.
+#[derive(Clone)]
+struct SizeFunction(Arc<dyn Fn(f64) -> f64 + Send + Sync>);
+
+impl SizeFunction {
+    fn size(&self, time: f64) -> f64 {
+        let size = (self.0)(time);
+        assert!(size > 0.0, "The size of the population must be positive.");
+        size
+    }
+
+    /// Cumulative rate of coalescence between ``from`` and ``to``, by Simpson's rule.
+    fn simpson(&self, from: f64, to: f64) -> f64 {
+        let middle = from + (to - from) / 2.0;
+        (to - from) / 6.0 * (1.0 / self.size(from) + 4.0 / self.size(middle) + 1.0 / self.size(to))
+    }
+
+    /// Cumulative rate of coalescence between ``from`` and ``to``, by Simpson's
+    /// rule on both halves, and its estimated error.
+    fn integrate(&self, from: f64, to: f64) -> (f64, f64) {
+        let middle = from + (to - from) / 2.0;
+        let halves = self.simpson(from, middle) + self.simpson(middle, to);
+        (halves, (halves - self.simpson(from, to)).abs())
+    }
+
+    /// Adaptive integration step starting at time ``from``, of suggested length
+    /// ``length`` and ending at most at ``limit``. Returns its end, its rate of
+    /// coalescence and the suggested length of the next step.
+    fn step(&self, from: f64, mut length: f64, limit: f64) -> (f64, f64, f64) {
+        loop {
+            let to = (from + length).min(limit);
+            let (hazard, error) = self.integrate(from, to);
+            let negligible = to - from <= f64::EPSILON * from.abs().max(1.0);
+            if error <= INTEGRATION_TOLERANCE * hazard.max(f64::MIN_POSITIVE) || negligible {
+                return (to, hazard, 2.0 * length);
+            }
+            length /= 2.0;
+        }
+    }
+}
+
+impl core::fmt::Debug for SizeFunction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SizeFunction")
+    }
+}
+
+impl PartialEq for SizeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Period of time, from its start time until the start time of the next epoch,
+/// where the population has a constant relative size, changes exponentially
+/// or follows a given function.
+///
+/// With growth rate g, the relative size at time t of the epoch is
+/// size * exp(-g (t - start_time)). Since time goes backwards, a positive
+/// growth rate describes a population that has been growing towards the present.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epoch {
+    start_time: f64,
+    size: f64,
+    growth_rate: f64,
+    size_function: Option<SizeFunction>,
+}
+
+impl Epoch {
+    /// Creates a new Epoch.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative or ``size`` is not positive.
+    pub fn new(start_time: f64, size: f64) -> Self {
+        Epoch::exponential(start_time, size, 0.0)
+    }
+
+    /// Creates a new Epoch where the population size changes exponentially.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative, ``size`` is not positive or
+    /// ``growth_rate`` is not finite.
+    pub fn exponential(start_time: f64, size: f64, growth_rate: f64) -> Self {
+        assert!(start_time >= 0.0, "The start time of an epoch must be non-negative.");
+        assert!(size > 0.0, "The size of the population must be positive.");
+        assert!(growth_rate.is_finite(), "The growth rate must be finite.");
+
+        Epoch { start_time, size, growth_rate, size_function: None }
+    }
+
+    /// Creates a new Epoch where the relative size of the population at time
+    /// t, measured backwards from the present, is ``size_function(t)``.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative or ``size_function`` is not positive at
+    /// ``start_time``. Simulations panic if it is not positive at a later time.
+    pub fn variable<F>(start_time: f64, size_function: F) -> Self
+    where
+        F: Fn(f64) -> f64 + Send + Sync + 'static,
+    {
+        assert!(start_time >= 0.0, "The start time of an epoch must be non-negative.");
+        let size_function = SizeFunction(Arc::new(size_function));
+        let size = size_function.size(start_time);
+
+        Epoch { start_time, size, growth_rate: 0.0, size_function: Some(size_function) }
+    }
+
+    /// Time, measured backwards from the present, at which the epoch starts.
+    pub fn start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    /// Relative size of the population at the start of the epoch.
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    /// Exponential growth rate of the population during the epoch. It is
+    /// zero for epochs following a given function.
+    pub fn growth_rate(&self) -> f64 {
+        self.growth_rate
+    }
+
+    /// Relative size of the population at time ``time`` of the epoch.
+    pub fn size_at(&self, time: f64) -> f64 {
+        match &self.size_function {
+            Some(size_function) => size_function.size(time),
+            None => self.size * (-self.growth_rate * (time - self.start_time)).exp(),
+        }
+    }
+
+    /// Cumulative rate of coalescence, ∫ 1 / λ(s) ds, between times ``from`` and ``to``.
+    fn hazard(&self, from: f64, to: f64) -> f64 {
+        if let Some(size_function) = &self.size_function {
+            let mut hazard = 0.0;
+            let mut current_time = from;
+            let mut length = INITIAL_INTEGRATION_STEP * size_function.size(from);
+            while current_time < to {
+                let (step_end, step_hazard, next_length) = size_function.step(current_time, length, to);
+                hazard += step_hazard;
+                current_time = step_end;
+                length = next_length;
+            }
+            return hazard;
+        }
+
+        let duration = to - from;
+        if self.growth_rate == 0.0 {
+            duration / self.size
+        } else {
+            (self.growth_rate * duration).exp_m1() / (self.growth_rate * self.size_at(from))
+        }
+    }
+
+    /// Time needed, starting at time ``from``, to accumulate a rate of coalescence
+    /// ``hazard``. It is infinite if the epoch never accumulates that much.
+    ///
+    /// For epochs following a given function, it is infinite only if the
+    /// hazard is not reached before time overflows.
+    fn inverse_hazard(&self, from: f64, hazard: f64) -> f64 {
+        if let Some(size_function) = &self.size_function {
+            // Integrate step by step until the hazard is reached
+
+            let mut remaining = hazard;
+            let mut current_time = from;
+            let mut length = INITIAL_INTEGRATION_STEP * size_function.size(from);
+            while (current_time + length).is_finite() {
+                let (step_end, step_hazard, next_length) = size_function.step(current_time, length, f64::INFINITY);
+                assert!(step_hazard.is_finite(), "The integration of the size function did not converge.");
+                if remaining > step_hazard {
+                    remaining -= step_hazard;
+                    current_time = step_end;
+                    length = next_length;
+                    continue;
+                }
+
+                // Bisection inside the last step
+
+                let (mut lower, mut upper) = (current_time, step_end);
+                while upper - lower > f64::EPSILON * upper.abs().max(1.0) {
+                    let middle = lower + (upper - lower) / 2.0;
+                    if size_function.integrate(current_time, middle).0 < remaining {
+                        lower = middle;
+                    } else {
+                        upper = middle;
+                    }
+                }
+                return lower + (upper - lower) / 2.0 - from;
+            }
+            return f64::INFINITY;
+        }
+
+        if self.growth_rate == 0.0 {
+            hazard * self.size
+        } else {
+            let argument = hazard * self.growth_rate * self.size_at(from);
+            if argument <= -1.0 {
+                f64::INFINITY
+            } else {
+                argument.ln_1p() / self.growth_rate
+            }
+        }
+    }
+}
+
+/// Instantaneous reduction of the population size.
+///
+/// At its time, the lineages coalesce as in a standard coalescent running for
+/// ``intensity`` units of time, but all mergers happen in an instant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bottleneck {
+    time: f64,
+    intensity: f64,
+}
+
+impl Bottleneck {
+    /// Creates a new Bottleneck.
+    ///
+    /// # Panics
+    ///
+    /// If ``time`` or ``intensity`` are not positive.
+    pub fn new(time: f64, intensity: f64) -> Self {
+        assert!(time > 0.0, "The time of a bottleneck must be positive.");
+        assert!(intensity > 0.0, "The intensity of a bottleneck must be positive.");
+
+        Bottleneck { time, intensity }
+    }
+
+    /// Time, measured backwards from the present, at which the bottleneck happens.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Amount of coalescent time compressed into the bottleneck.
+    pub fn intensity(&self) -> f64 {
+        self.intensity
+    }
+}
+
+/// Population size history, given by a sequence of epochs of constant,
+/// exponentially changing or arbitrary size and instantaneous bottlenecks.
+///
+/// Times are measured backwards from the present, in coalescent units of
+/// a population of relative size one. The first epoch always starts at time zero.
+///
+/// # Examples
+///
+/// A population that was ten times smaller between times 0.5 and 1.0.
+/// ```
+/// let mut demography = coalescence::Demography::default();
+/// demography.add_epoch(0.5, 0.1).add_epoch(1.0, 1.0);
+///
+/// assert_eq!(demography.size(0.2), 1.0);
+/// assert_eq!(demography.size(0.7), 0.1);
+/// assert_eq!(demography.size(3.0), 1.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Demography {
+    epochs: Vec<Epoch>, // sorted by start time
+    bottlenecks: Vec<Bottleneck>, // sorted by time
+}
+
+impl Demography {
+    /// Creates a new Demography of a population of constant relative size.
+    ///
+    /// # Panics
+    ///
+    /// If ``size`` is not positive.
+    pub fn new(size: f64) -> Self {
+        Demography { epochs: vec![Epoch::new(0.0, size)], bottlenecks: Vec::new() }
+    }
+
+    /// Creates a new Demography of a population of present relative size ``size``
+    /// changing exponentially with growth rate ``growth_rate``.
+    ///
+    /// # Panics
+    ///
+    /// If ``size`` is not positive or ``growth_rate`` is not finite.
+    ///
+    /// # Examples
+    ///
+    /// A population that has been growing since time 1.0, before which it
+    /// had a constant size.
+    /// ```
+    /// let mut demography = coalescence::Demography::exponential(1.0, 2.0);
+    /// let ancestral_size = demography.size(1.0);
+    /// demography.add_epoch(1.0, ancestral_size);
+    ///
+    /// assert_eq!(demography.size(0.0), 1.0);
+    /// assert_eq!(demography.size(1.0), (-2.0_f64).exp());
+    /// assert_eq!(demography.size(5.0), (-2.0_f64).exp());
+    /// ```
+    pub fn exponential(size: f64, growth_rate: f64) -> Self {
+        Demography { epochs: vec![Epoch::exponential(0.0, size, growth_rate)], bottlenecks: Vec::new() }
+    }
+
+    /// Adds a new epoch, where the population changes to relative size ``size``
+    /// at time ``start_time``. If there was already an epoch starting at this
+    /// time, it is replaced.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative or ``size`` is not positive.
+    pub fn add_epoch(&mut self, start_time: f64, size: f64) -> &mut Self {
+        self.insert_epoch(Epoch::new(start_time, size))
+    }
+
+    /// Adds a new epoch, where the population changes to relative size ``size``
+    /// at time ``start_time`` and from then on changes exponentially with
+    /// growth rate ``growth_rate``. If there was already an epoch starting at
+    /// this time, it is replaced.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative, ``size`` is not positive or
+    /// ``growth_rate`` is not finite.
+    pub fn add_exponential_epoch(&mut self, start_time: f64, size: f64, growth_rate: f64) -> &mut Self {
+        self.insert_epoch(Epoch::exponential(start_time, size, growth_rate))
+    }
+
+    fn insert_epoch(&mut self, epoch: Epoch) -> &mut Self {
+        let start_time = epoch.start_time;
+        match self.epochs.iter().position(|e| e.start_time >= start_time) {
+            Some(index) if self.epochs[index].start_time == start_time => self.epochs[index] = epoch,
+            Some(index) => self.epochs.insert(index, epoch),
+            None => self.epochs.push(epoch),
+        }
+        self
+    }
+
+    /// Creates a new Demography of a population whose relative size at time t,
+    /// measured backwards from the present, is ``size_function(t)``. Any
+    /// closure or boxed trait object can be used, as long as it is
+    /// ``Send + Sync + 'static``, e.g. ``Box<dyn Fn(f64) -> f64 + Send + Sync>``.
+    ///
+    /// # Panics
+    ///
+    /// If ``size_function(0.0)`` is not positive. Simulations panic if it is
+    /// not positive at a later time.
+    ///
+    /// # Remarks
+    ///
+    /// Each waiting time integrates the rate of coalescence with adaptive
+    /// steps, whose length doubles while the function is smooth, and costs a
+    /// few evaluations of ``size_function`` per step. Integration continues
+    /// until the required rate is accumulated, so a waiting time is infinite
+    /// only if that rate is not reached before time overflows ``f64``.
+    ///
+    /// # Examples
+    ///
+    /// A population with seasonal oscillations of its size.
+    /// ```
+    /// let demography = coalescence::Demography::variable(|t: f64| 1.5 + (10.0 * t).sin());
+    ///
+    /// assert_eq!(demography.size(0.0), 1.5);
+    /// let waiting_time = demography.waiting_time(0.0, 1.0);
+    /// assert!(waiting_time > 0.0);
+    /// ```
+    pub fn variable<F>(size_function: F) -> Self
+    where
+        F: Fn(f64) -> f64 + Send + Sync + 'static,
+    {
+        Demography { epochs: vec![Epoch::variable(0.0, size_function)], bottlenecks: Vec::new() }
+    }
+
+    /// Adds a new epoch, where from time ``start_time`` on the relative size
+    /// of the population at time t is ``size_function(t)``. If there was
+    /// already an epoch starting at this time, it is replaced.
+    ///
+    /// # Panics
+    ///
+    /// If ``start_time`` is negative or ``size_function(start_time)`` is not positive.
+    pub fn add_variable_epoch<F>(&mut self, start_time: f64, size_function: F) -> &mut Self
+    where
+        F: Fn(f64) -> f64 + Send + Sync + 'static,
+    {
+        self.insert_epoch(Epoch::variable(start_time, size_function))
+    }
+
+    /// Adds a bottleneck at time ``time`` with intensity ``intensity``. If there
+    /// was already a bottleneck at this time, it is replaced.
+    ///
+    /// # Panics
+    ///
+    /// If ``time`` or ``intensity`` are not positive.
+    ///
+    /// # Examples
+    ///
+    /// A strong bottleneck merges most lineages at once.
+    /// ```
+    /// let mut demography = coalescence::Demography::default();
+    /// demography.add_bottleneck(0.1, 5.0);
+    ///
+    /// assert_eq!(demography.next_bottleneck(0.0).unwrap().time(), 0.1);
+    /// assert!(demography.next_bottleneck(0.1).is_none());
+    /// ```
+    pub fn add_bottleneck(&mut self, time: f64, intensity: f64) -> &mut Self {
+        let bottleneck = Bottleneck::new(time, intensity);
+        match self.bottlenecks.iter().position(|b| b.time >= time) {
+            Some(index) if self.bottlenecks[index].time == time => self.bottlenecks[index] = bottleneck,
+            Some(index) => self.bottlenecks.insert(index, bottleneck),
+            None => self.bottlenecks.push(bottleneck),
+        }
+        self
+    }
+
+    /// Epochs of the demography, sorted by start time.
+    pub fn epochs(&self) -> &[Epoch] {
+        &self.epochs
+    }
+
+    /// Bottlenecks of the demography, sorted by time.
+    pub fn bottlenecks(&self) -> &[Bottleneck] {
+        &self.bottlenecks
+    }
+
+    /// First bottleneck happening strictly after time ``time``.
+    pub fn next_bottleneck(&self, time: f64) -> Option<&Bottleneck> {
+        self.bottlenecks.iter().find(|bottleneck| bottleneck.time > time)
+    }
+
+    /// Relative size of the population at time ``time``.
+    pub fn size(&self, time: f64) -> f64 {
+        self.epochs[self.epoch_index(time)].size_at(time)
+    }
+
+    /// Transforms a waiting time of a population of constant relative size one,
+    /// starting at time ``time``, into the waiting time of this demography.
+    ///
+    /// # Remarks
+    ///
+    /// The waiting time is rescaled epoch by epoch: in an epoch of constant
+    /// relative size λ, a unit of time of the standard coalescent corresponds
+    /// to λ units of time. In epochs of exponential change, the cumulative rate
+    /// of coalescence is inverted exactly. In epochs following a given function,
+    /// it is integrated by Simpson's rule and inverted by bisection.
+    ///
+    /// If the population grows fast enough into the past, the waiting
+    /// time can be infinite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut demography = coalescence::Demography::new(2.0);
+    /// demography.add_epoch(1.0, 0.5);
+    ///
+    /// // Half a unit is consumed before time 1.0, the rest after it.
+    /// assert_eq!(demography.waiting_time(0.0, 1.0), 1.25);
+    /// assert_eq!(demography.waiting_time(2.0, 1.0), 0.5);
+    /// ```
+    pub fn waiting_time(&self, time: f64, standard_waiting_time: f64) -> f64 {
+        let mut remaining = standard_waiting_time;
+        let mut elapsed = 0.0;
+        let mut current_time = time;
+
+        for index in self.epoch_index(time)..self.epochs.len() {
+            let epoch = &self.epochs[index];
+            match self.epochs.get(index + 1) {
+                Some(next_epoch) => {
+                    let epoch_hazard = epoch.hazard(current_time, next_epoch.start_time);
+                    if remaining <= epoch_hazard {
+                        return elapsed + epoch.inverse_hazard(current_time, remaining);
+                    }
+                    remaining -= epoch_hazard;
+                    elapsed += next_epoch.start_time - current_time;
+                    current_time = next_epoch.start_time;
+                },
+                None => return elapsed + epoch.inverse_hazard(current_time, remaining),
+            }
+        }
+
+        elapsed
+    }
+
+    fn epoch_index(&self, time: f64) -> usize {
+        self.epochs
+            .iter()
+            .rposition(|epoch| epoch.start_time <= time)
+            .unwrap_or(0)
+    }
+}
+
+impl Default for Demography {
+    /// Population of constant relative size one, the standard coalescent.
+    fn default() -> Self {
+        Demography::new(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_size() {
+        let demography = Demography::default();
+        assert_eq!(demography.waiting_time(0.0, 0.3), 0.3);
+        assert_eq!(demography.waiting_time(5.0, 0.3), 0.3);
+    }
+
+    #[test]
+    fn epochs_sorted() {
+        let mut demography = Demography::default();
+        demography.add_epoch(2.0, 3.0).add_epoch(1.0, 0.5).add_epoch(2.0, 4.0);
+
+        let start_times: Vec<f64> = demography.epochs().iter().map(|e| e.start_time()).collect();
+        assert_eq!(start_times, vec![0.0, 1.0, 2.0]);
+        assert_eq!(demography.size(2.5), 4.0);
+    }
+
+    #[test]
+    fn crossing_epochs() {
+        let mut demography = Demography::default();
+        demography.add_epoch(1.0, 0.1).add_epoch(2.0, 10.0);
+
+        // 1.0 in the first epoch, 10.0 standard units in the second
+        // and the remaining 0.5 in the last one.
+        let waiting_time = demography.waiting_time(0.0, 11.5);
+        assert!((waiting_time - 7.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn exponential_growth() {
+        let growth_rate = 3.0;
+        let demography = Demography::exponential(1.0, growth_rate);
+
+        // Exact inverse of the cumulative rate (exp(g t) - 1) / g.
+        let waiting_time = demography.waiting_time(0.0, 0.7);
+        assert!((waiting_time - (1.0 + 0.7 * growth_rate).ln() / growth_rate).abs() < 1e-10);
+
+        // Continuing from a later time gives the same total time.
+        let first = demography.waiting_time(0.0, 0.3);
+        let second = demography.waiting_time(first, 0.4);
+        assert!((first + second - waiting_time).abs() < 1e-10);
+    }
+
+    #[test]
+    fn exponential_then_constant() {
+        let mut demography = Demography::exponential(1.0, 2.0);
+        let ancestral_size = demography.size(1.0);
+        demography.add_epoch(1.0, ancestral_size);
+
+        let hazard_first_epoch = (2.0_f64).exp_m1() / 2.0;
+        let waiting_time = demography.waiting_time(0.0, hazard_first_epoch + 1.0);
+        assert!((waiting_time - (1.0 + ancestral_size)).abs() < 1e-10);
+    }
+
+    #[test]
+    fn exponential_decline_can_be_infinite() {
+        let demography = Demography::exponential(1.0, -1.0);
+        assert!(demography.waiting_time(0.0, 0.5).is_finite());
+        assert!(demography.waiting_time(0.0, 1.0).is_infinite());
+    }
+
+    #[test]
+    fn exponential_decline_can_stop_the_coalescent() {
+        use rand::SeedableRng;
+
+        let mut stopped = 0;
+        for seed in 0..100 {
+            let mut coalescent = crate::Coalescent::new(2, rand_pcg::Pcg32::seed_from_u64(seed));
+            coalescent.set_demography(Demography::exponential(1.0, -5.0));
+            match coalescent.next_step() {
+                Some((time_step, step)) => {
+                    assert!(time_step.is_finite());
+                    assert_eq!(step, vec![vec![0, 1]]);
+                },
+                None => stopped += 1,
+            }
+        }
+        assert!(stopped > 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "never coalesce")]
+    fn exponential_decline_has_no_genealogy() {
+        use rand::SeedableRng;
+
+        let mut coalescent = crate::Coalescent::new(2, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.set_demography(Demography::exponential(1.0, -1000.0));
+        coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(0));
+    }
+
+    #[test]
+    fn variable_size_matches_exact_epochs() {
+        let exponential = Demography::exponential(1.0, 2.0);
+        let variable = Demography::variable(|t: f64| (-2.0 * t).exp());
+        for &hazard in &[0.01, 0.5, 3.0] {
+            let exact = exponential.waiting_time(0.2, hazard);
+            assert!((variable.waiting_time(0.2, hazard) - exact).abs() < 1e-8);
+        }
+
+        let mut piecewise = Demography::default();
+        piecewise.add_variable_epoch(1.0, |_| 0.1).add_epoch(2.0, 10.0);
+        let waiting_time = piecewise.waiting_time(0.0, 11.5);
+        assert!((waiting_time - 7.0).abs() < 1e-8);
+    }
+
+    #[test]
+    fn variable_size_can_be_infinite() {
+        let demography = Demography::variable(|t: f64| (t * t).exp());
+        assert!(demography.waiting_time(0.0, 100.0).is_infinite());
+
+        let size_function: Box<dyn Fn(f64) -> f64 + Send + Sync> = Box::new(|t: f64| 1.0 + t);
+        let demography = Demography::variable(size_function);
+        assert!(demography.waiting_time(0.0, 1e6).is_infinite());
+        let waiting_time = demography.waiting_time(0.0, 2.0);
+        assert!((waiting_time - 2.0_f64.exp_m1()).abs() < 1e-8);
+    }
+
+    #[test]
+    fn variable_size_oscillating_is_finite() {
+        let oscillating = Demography::variable(|t: f64| 1000.0 + (100.0 * t).sin());
+        let waiting_time = oscillating.waiting_time(0.0, 1.0);
+        assert!((waiting_time - 1000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn bottlenecks_sorted() {
+        let mut demography = Demography::default();
+        demography.add_bottleneck(2.0, 1.0).add_bottleneck(1.0, 0.5).add_bottleneck(2.0, 3.0);
+
+        let times: Vec<f64> = demography.bottlenecks().iter().map(|b| b.time()).collect();
+        assert_eq!(times, vec![1.0, 2.0]);
+        assert_eq!(demography.next_bottleneck(1.5).unwrap().intensity(), 3.0);
+    }
+}
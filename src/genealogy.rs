@@ -12,6 +12,83 @@ use std::collections::HashMap;
 
 // Traits
 use std::iter::FromIterator;
+use std::ops::Add;
+
+/// Simultaneous mergers of sets of a partition, happening at the same time. 
+/// 
+/// Each merger is given by one value index of each of the (at least two) sets 
+/// that are joint. Different mergers involve different sets. 
+pub type Step = Vec<Vec<usize>>;
+
+/// Joins the sets of ``partition`` as indicated by ``step``.
+pub(crate) fn apply_step(partition: &mut PartitionVec<()>, step: &Step) {
+	for merger in step {
+		for &value_index in &merger[1..] {
+			partition.union(merger[0], value_index);
+		}
+	}
+}
+
+/// Folds a site frequency spectrum of a group of individuals, given by index i - 1 
+/// for i = 1, ..., n - 1, into counts of the minor allele i = 1, ..., n / 2. 
+pub(crate) fn fold<T>(spectrum: &[T]) -> Vec<T> 
+where
+	T: Copy + Add<Output = T>,
+{
+	let group_size = spectrum.len() + 1;
+	(1..=group_size / 2)
+		.map(|i| match 2 * i == group_size {
+			true => spectrum[i - 1],
+			false => spectrum[i - 1] + spectrum[group_size - i - 1],
+		})
+		.collect()
+}
+
+/// Edge of a genealogic tree, from a node to its parent. 
+/// 
+/// Nodes are labelled as in the graph of the genealogy: by the generation, i.e. 
+/// the index of the state in the path, at which they appear and the smallest 
+/// individual they are ancestral to. 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+	generation: usize,
+	representative: usize,
+	start_time: f64,
+	end_time: f64,
+	descendants: Vec<usize>, // sorted
+}
+
+impl Branch {
+	/// Generation at which the lower node of the branch appears. 
+	pub fn generation(&self) -> usize {
+		self.generation
+	}
+
+	/// Smallest individual descending from the branch. 
+	pub fn representative(&self) -> usize {
+		self.representative
+	}
+
+	/// Time of the lower node of the branch. 
+	pub fn start_time(&self) -> f64 {
+		self.start_time
+	}
+
+	/// Time of the upper node of the branch, where it merges with others. 
+	pub fn end_time(&self) -> f64 {
+		self.end_time
+	}
+
+	/// Time represented in the branch. 
+	pub fn length(&self) -> f64 {
+		self.end_time - self.start_time
+	}
+
+	/// Individuals descending from the branch, sorted. 
+	pub fn descendants(&self) -> &[usize] {
+		&self.descendants
+	}
+}
 
 /// Genealogic tree. 
 /// 
@@ -20,33 +97,117 @@ use std::iter::FromIterator;
 #[derive(Debug, Clone)]
 pub struct Genealogy {
 	path: Vec<PartitionVec<()>>, // including initial state
-	steps: Vec<[usize; 2]>,
-	time_steps: Vec<f64>, // all positive intervals
+	steps: Vec<Step>,
+	time_steps: Vec<f64>, // all non-negative intervals
+	demes: Option<Vec<Vec<usize>>>, // deme of each individual's lineage, for each state of the path
+	sampling_times: Vec<f64>, // time at which each individual was sampled
 	graph: Option<Graph<(usize, usize), f64, petgraph::Undirected, u32>>,
 }
 
 impl Genealogy {
 
-	pub(crate) fn new(path: Vec<PartitionVec<()>>, steps: Vec<[usize; 2]>, time_steps: Vec<f64>) -> Self {
+	pub(crate) fn new(path: Vec<PartitionVec<()>>, steps: Vec<Step>, time_steps: Vec<f64>) -> Self {
+		let demes = None;
+		let sampling_times = vec![0.0; path[0].len()];
 		let graph = None;
 
-		Genealogy{path, steps, time_steps, graph}
+		Genealogy{path, steps, time_steps, demes, sampling_times, graph}
+	}
+
+	/// Creates a genealogy of ``group_size`` individuals from the steps and the 
+	/// times at which they happen, sorted by time. 
+	pub(crate) fn from_timed_steps(group_size: usize, timed_steps: Vec<(f64, Step)>) -> Self {
+		let mut state: PartitionVec<()> = PartitionVec::from_iter((0..group_size).map(|_| ()));
+		let mut path = vec![state.clone()];
+		let mut steps = Vec::with_capacity(timed_steps.len());
+		let mut time_steps = Vec::with_capacity(timed_steps.len());
+		let mut current_time = 0.0;
+
+		for (time, step) in timed_steps {
+			apply_step(&mut state, &step);
+			path.push(state.clone());
+			steps.push(step);
+			time_steps.push(time - current_time);
+			current_time = time;
+		}
+
+		Genealogy::new(path, steps, time_steps)
+	}
+
+	/// Records the deme of the lineage of each individual, for each state of the path. 
+	pub(crate) fn with_demes(mut self, demes: Vec<Vec<usize>>) -> Self {
+		self.demes = Some(demes);
+		self
+	}
+
+	/// Records the time, measured backwards from the present, at which each 
+	/// individual was sampled. 
+	pub(crate) fn with_sampling_times(mut self, sampling_times: Vec<f64>) -> Self {
+		self.sampling_times = sampling_times;
+		self
+	}
+
+	/// Number of individuals in the group, i.e. leaves of the tree. 
+	pub fn group_size(&self) -> usize {
+		self.path[0].len()
+	}
+
+	/// Sequence of partitions of the group, from the initial partition of 
+	/// singletons to the partition with only one set. 
+	pub fn path(&self) -> &[PartitionVec<()>] {
+		&self.path
+	}
+
+	/// Mergers that produce each partition of the path from the previous one. 
+	pub fn steps(&self) -> &[Step] {
+		&self.steps
+	}
+
+	/// Time elapsed before each step. 
+	pub fn time_steps(&self) -> &[f64] {
+		&self.time_steps
+	}
+
+	/// Deme of the lineage of each individual, for each state of the path. 
+	/// 
+	/// Only genealogies of structured populations have demes. Steps without 
+	/// mergers are migrations of lineages.
+	pub fn demes(&self) -> Option<&[Vec<usize>]> {
+		self.demes.as_deref()
+	}
+
+	/// Deme from which each individual was sampled, i.e. the label of each leaf. 
+	/// 
+	/// Only genealogies of structured populations have demes. 
+	pub fn sample_demes(&self) -> Option<&[usize]> {
+		self.demes.as_ref().map(|demes| &demes[0][..])
+	}
+
+	/// Time, measured backwards from the present, at which each individual 
+	/// was sampled. All zero unless the individuals were serially sampled. 
+	pub fn sampling_times(&self) -> &[f64] {
+		&self.sampling_times
 	}
 
 	/// Total depth of the tree, i.e. the distance from the first common ancestor
-	/// of the group. 
+	/// of the group to the present. 
 	pub fn depth(&self) -> f64 {
 		self.time_steps.iter().sum()
 	}
 
 	/// Sum of all the time represented in the edges of the genealogy. 
+	/// 
+	/// # Remarks
+	/// 
+	/// The edge of each individual starts at its sampling time. 
 	pub fn length(&self) -> f64 {
-		let group_size = self.path[0].len();
-		self.time_steps
+		let from_present: f64 = self.time_steps
 			.iter()
 			.enumerate()
-			.map(|(i, time_step)| (group_size - i) as f64 * time_step )
-			.sum()
+			.map(|(i, time_step)| self.path[i].amount_of_sets() as f64 * time_step )
+			.sum();
+
+		from_present - self.sampling_times.iter().sum::<f64>()
 	}
 
 	/// Distance between two individuals in the genealogic tree. 
@@ -66,43 +227,190 @@ impl Genealogy {
 		}
 
 		2.0 * self.time_steps.iter().take(counter).sum::<f64>()
+			- self.sampling_times[index_1] - self.sampling_times[index_2]
 	}
 
 	/// Mean distance of all pairs of individual through their first common ancestor, i.e. 
 	/// mean distance of all pairs of leaves in the tree. 
 	pub fn mean_pairwise_divergence(&self) -> f64 {
-		let group_size = self.steps.len() + 1;
+		let group_size = self.group_size();
 		let mut cummulative_time = 0.0;
 		let mut cummulative_divergence = 0.0;
 
-		for iteration in 0..(group_size - 1) {
+		for iteration in 0..self.steps.len() {
 			// Retrieve values
 
-			let value_indexes = self.steps[iteration];
 			let state = &self.path[iteration];
 			cummulative_time += self.time_steps[iteration];
 
-			// Identify the lengths of sets joints
+			for merger in &self.steps[iteration] {
+				// Identify the lengths of sets joints
+
+				let set_sizes: Vec<usize> = merger.iter()
+					.map(|&value_index| state.len_of_set(value_index))
+					.collect();
+				
+				// Count number of pairs
+
+				let total_size: usize = set_sizes.iter().sum();
+				let squared_sizes: usize = set_sizes.iter().map(|size| size * size).sum();
+				let number_of_pairs = (total_size * total_size - squared_sizes) / 2;
+
+				// Add to the counter the respective time
+
+				cummulative_divergence += (2.0 * cummulative_time) * number_of_pairs as f64;
+			}
+		}
+
+		// Take mean, each individual being in (group_size - 1) pairs
+
+		let mean_sampling_time = self.sampling_times.iter().sum::<f64>() / group_size as f64;
+		cummulative_divergence * 2.0 / (group_size * (group_size - 1)) as f64 - 2.0 * mean_sampling_time
+	}
+
+	/// Mean distance between pairs of different individuals, one sampled from 
+	/// ``deme_1`` and the other from ``deme_2``. If both demes are the same, 
+	/// it is the mean distance of pairs within the deme.
+	/// 
+	/// # Panics
+	/// 
+	/// If the genealogy has no demes, see ``demes`` method.
+	/// 
+	/// # Remarks
+	/// 
+	/// If there are no such pairs, the result is NaN. 
+	pub fn mean_divergence_between(&self, deme_1: usize, deme_2: usize) -> f64 {
+		let sample_demes = &self.demes.as_ref().expect("The genealogy has no demes.")[0];
+		let in_deme = |index: usize, deme: usize| sample_demes[index] == deme;
+		let mut cummulative_time = 0.0;
+		let mut cummulative_divergence = 0.0;
+		let mut number_of_pairs = 0;
+
+		for iteration in 0..self.steps.len() {
+			let state = &self.path[iteration];
+			cummulative_time += self.time_steps[iteration];
+
+			for merger in &self.steps[iteration] {
+				let sets: Vec<Vec<usize>> = merger.iter()
+					.map(|&value_index| state.set(value_index).map(|(i, _)| i).collect())
+					.collect();
+
+				// Count pairs between different sets of the merger
+
+				for first in 0..sets.len() {
+					for second in (first + 1)..sets.len() {
+						for &i in &sets[first] {
+							for &j in &sets[second] {
+								if (in_deme(i, deme_1) && in_deme(j, deme_2)) || (in_deme(i, deme_2) && in_deme(j, deme_1)) {
+									cummulative_divergence += 2.0 * cummulative_time
+										- self.sampling_times[i] - self.sampling_times[j];
+									number_of_pairs += 1;
+								}
+							}
+						}
+					}
+				}
+			}
+		}
 
-			let set_size_1 = state.len_of_set(value_indexes[0]);
-			let set_size_2 = state.len_of_set(value_indexes[1]); 
-			
-			// Count number of pairs
+		cummulative_divergence / number_of_pairs as f64
+	}
 
-			let number_of_pairs = set_size_1 * set_size_2;
+	/// All branches of the genealogic tree, sorted by the time at which they end. 
+	/// 
+	/// # Examples
+	/// 
+	/// The branches add up to the length of the genealogy. 
+	/// ```
+	/// let rng = rand::thread_rng();
+	/// let coalescent = coalescence::Coalescent::new(10, rng);
+	/// let mut rng = rand::thread_rng();
+	/// let genealogy = coalescent.sample_genealogy(&mut rng);
+	/// 
+	/// let branches = genealogy.branches();
+	/// assert_eq!(branches.len(), 2 * 10 - 2);
+	/// let length: f64 = branches.iter().map(|branch| branch.length()).sum();
+	/// assert!((length - genealogy.length()).abs() < 1e-10);
+	/// ```
+	pub fn branches(&self) -> Vec<Branch> {
+		let mut nodes: Vec<(usize, f64)> = self.sampling_times.iter().map(|&time| (0, time)).collect(); // lower node of each representative
+		let mut branches = Vec::new();
+		let mut time = 0.0;
+
+		for generation in 0..self.steps.len() {
+			let state = &self.path[generation];
+			time += self.time_steps[generation];
+
+			for merger in &self.steps[generation] {
+				// Sets joint
+
+				let sets: Vec<Vec<usize>> = merger.iter()
+					.map(|&value_index| {
+						let mut set: Vec<usize> = state.set(value_index).map(|(i, _)| i).collect();
+						set.sort_unstable();
+						set
+					})
+					.collect();
+
+				// Add their branches
+
+				for set in sets.iter() {
+					let (child_generation, child_time) = nodes[set[0]];
+					branches.push(Branch {
+						generation: child_generation,
+						representative: set[0],
+						start_time: child_time,
+						end_time: time,
+						descendants: set.clone(),
+					});
+				}
 
-			// Add to the counter the respective time
+				// Update
 
-			cummulative_divergence += (2.0 * cummulative_time) * number_of_pairs as f64;
+				let new_representative = sets.iter().map(|set| set[0]).min().unwrap();
+				nodes[new_representative] = (generation + 1, time);
+			}
 		}
 
-		// Take mean
+		branches
+	}
 
-		cummulative_divergence * 2.0 / (group_size * (group_size - 1)) as f64
+	/// Total length of the branches subtending exactly i individuals, at index 
+	/// i - 1 for i = 1, ..., n - 1. It adds up to the length of the genealogy. 
+	/// 
+	/// # Remarks
+	/// 
+	/// Under the infinite-sites model with mutation rate θ, the expected site 
+	/// frequency spectrum given the genealogy is θ / 2 times this spectrum. 
+	/// 
+	/// # Examples
+	/// 
+	/// ```
+	/// let rng = rand::thread_rng();
+	/// let coalescent = coalescence::Coalescent::new(10, rng);
+	/// let mut rng = rand::thread_rng();
+	/// let genealogy = coalescent.sample_genealogy(&mut rng);
+	/// 
+	/// let spectrum = genealogy.length_spectrum();
+	/// assert_eq!(spectrum.len(), 9);
+	/// assert!((spectrum.iter().sum::<f64>() - genealogy.length()).abs() < 1e-10);
+	/// ```
+	pub fn length_spectrum(&self) -> Vec<f64> {
+		let mut spectrum = vec![0.0; self.group_size().saturating_sub(1)];
+		for branch in self.branches() {
+			spectrum[branch.descendants().len() - 1] += branch.length();
+		}
+		spectrum
+	}
+
+	/// Folded version of ``length_spectrum``: total length of the branches 
+	/// subtending either i or n - i individuals, at index i - 1 for i = 1, ..., n / 2. 
+	pub fn folded_length_spectrum(&self) -> Vec<f64> {
+		fold(&self.length_spectrum())
 	}
 
 	fn compute_graph(&mut self) -> &Graph<(usize, usize), f64, petgraph::Undirected, u32> { 
-		let group_size = self.steps.len() + 1;
+		let group_size = self.group_size();
 		let mut graph = Graph::new_undirected();
 		let mut node_indexes = HashMap::new();
 		let mut representatives_generation = HashMap::new();
@@ -114,6 +422,7 @@ impl Genealogy {
 			false => {
 				let mut state: PartitionVec<()> =
             		PartitionVec::from_iter((0..group_size).map(|_| ()));
+				let mut generation_times = vec![0.0];
 				for index in 0..group_size {
 					let node_index = graph.add_node((0, index));
 					node_indexes.insert((0, index), node_index);
@@ -121,39 +430,43 @@ impl Genealogy {
 				}
 
 				for generation in 0..self.steps.len() {
-					let time_step = self.time_steps[generation];
-					let value_indexes = self.steps[generation];
-
-					// Retrieve representatives
-
-					let representatives: Vec<usize> = value_indexes.iter()
-						.map(|&i| (0..group_size).find(|&j| state.same_set(i, j))
-							.expect("Could not retrieve previous state of the genealogy.")
-						).collect();
-
-					// Add parent node
-
-					let new_representative = core::cmp::min(representatives[0], representatives[1]);
-					let node_index = graph.add_node((generation + 1, new_representative));
-					node_indexes.insert((generation + 1, new_representative), node_index);
-					
-					// Add edges
-
-					graph.add_edge(
-						node_indexes[&(generation + 1, new_representative)], 
-						node_indexes[&(representatives_generation[&representatives[0]], representatives[0])], 
-						time_step
-					);
-					graph.add_edge(
-						node_indexes[&(generation + 1, new_representative)], 
-						node_indexes[&(representatives_generation[&representatives[1]], representatives[1])], 
-						time_step
-					);
-
-					// Update
-
-					representatives_generation.insert(new_representative, generation + 1);
-					state.union(value_indexes[0], value_indexes[1]);
+					let time = generation_times[generation] + self.time_steps[generation];
+					generation_times.push(time);
+
+					for merger in &self.steps[generation] {
+						// Retrieve representatives
+
+						let representatives: Vec<usize> = merger.iter()
+							.map(|&i| (0..group_size).find(|&j| state.same_set(i, j))
+								.expect("Could not retrieve previous state of the genealogy.")
+							).collect();
+
+						// Add parent node
+
+						let new_representative = *representatives.iter().min().unwrap();
+						let node_index = graph.add_node((generation + 1, new_representative));
+						node_indexes.insert((generation + 1, new_representative), node_index);
+						
+						// Add edges
+
+						for representative in representatives {
+							let child_generation = representatives_generation[&representative];
+							let child_time = match child_generation {
+								0 => self.sampling_times[representative],
+								_ => generation_times[child_generation],
+							};
+							graph.add_edge(
+								node_index, 
+								node_indexes[&(child_generation, representative)], 
+								time - child_time
+							);
+						}
+
+						// Update
+
+						representatives_generation.insert(new_representative, generation + 1);
+					}
+					apply_step(&mut state, &self.steps[generation]);
 				}
 			},
 		}
@@ -191,4 +504,65 @@ mod tests {
 		assert_eq!(genealogy.time_steps.len(), group_size - 1);
 		assert!(genealogy.graph.is_none());
 	}
+
+	#[test]
+	fn simultaneous_mergers() {
+		// ((0, 1, 2), (3, 4)) at time 1.0, then the root at time 3.0.
+		let mut state: PartitionVec<()> = PartitionVec::from_iter((0..5).map(|_| ()));
+		let mut path = vec![state.clone()];
+		let steps = vec![vec![vec![0, 1, 2], vec![3, 4]], vec![vec![0, 3]]];
+		for step in &steps {
+			apply_step(&mut state, step);
+			path.push(state.clone());
+		}
+		let genealogy = Genealogy::new(path, steps, vec![1.0, 2.0]);
+
+		assert_eq!(genealogy.depth(), 3.0);
+		assert_eq!(genealogy.length(), 5.0 * 1.0 + 2.0 * 2.0);
+		assert_eq!(genealogy.divergence(1, 2), 2.0);
+		assert_eq!(genealogy.divergence(2, 4), 6.0);
+		// 4 pairs at divergence 2.0 and 6 pairs at divergence 6.0.
+		assert_eq!(genealogy.mean_pairwise_divergence(), (4.0 * 2.0 + 6.0 * 6.0) / 10.0);
+
+		let demed = genealogy.clone().with_demes(vec![vec![0, 0, 0, 1, 1]; 3]);
+		assert_eq!(demed.mean_divergence_between(0, 0), 2.0);
+		assert_eq!(demed.mean_divergence_between(0, 1), 6.0);
+		assert_eq!(demed.mean_divergence_between(1, 1), 2.0);
+
+		let branches = genealogy.branches();
+		assert_eq!(branches.len(), 5 + 2);
+		assert_eq!(branches[5].descendants(), &[0, 1, 2]);
+		assert_eq!((branches[6].generation(), branches[6].representative()), (1, 3));
+		assert_eq!(branches.iter().map(|branch| branch.length()).sum::<f64>(), genealogy.length());
+		assert_eq!(genealogy.length_spectrum(), vec![5.0, 2.0, 2.0, 0.0]);
+		assert_eq!(genealogy.folded_length_spectrum(), vec![5.0, 4.0]);
+
+		let graph: Graph<(usize, usize), f64, petgraph::Undirected, u32> = genealogy.into();
+		assert_eq!(graph.node_count(), 5 + 3);
+		assert_eq!(graph.raw_edges().iter().map(|edge| edge.weight).sum::<f64>(), 5.0 * 1.0 + 2.0 * 2.0);
+	}
+
+	#[test]
+	fn serial_samples() {
+		// (0, 1) at time 1.0, then the root at time 3.0, with 1 sampled at 0.5 and 2 at 2.0.
+		let mut state: PartitionVec<()> = PartitionVec::from_iter((0..3).map(|_| ()));
+		let mut path = vec![state.clone()];
+		let steps = vec![vec![vec![0, 1]], vec![vec![0, 2]]];
+		for step in &steps {
+			apply_step(&mut state, step);
+			path.push(state.clone());
+		}
+		let genealogy = Genealogy::new(path, steps, vec![1.0, 2.0])
+			.with_sampling_times(vec![0.0, 0.5, 2.0]);
+
+		assert_eq!(genealogy.depth(), 3.0);
+		assert_eq!(genealogy.length(), 1.0 + 0.5 + 1.0 + 2.0);
+		assert_eq!(genealogy.divergence(0, 1), 1.5);
+		assert_eq!(genealogy.divergence(1, 2), 3.5);
+		assert_eq!(genealogy.mean_pairwise_divergence(), (1.5 + 4.0 + 3.5) / 3.0);
+
+		let length = genealogy.length();
+		let graph: Graph<(usize, usize), f64, petgraph::Undirected, u32> = genealogy.into();
+		assert_eq!(graph.raw_edges().iter().map(|edge| edge.weight).sum::<f64>(), length);
+	}
 }
\ No newline at end of file
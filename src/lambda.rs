@@ -0,0 +1,427 @@
+//! Λ-coalescent process.
+//!
+//! Coalescent processes with multiple mergers, where any number of sets of the
+//! partition can merge at once. They are the limit of population models with
+//! high variance in the number of offspring, like sweepstakes reproduction.
+//! The process is given by a finite measure Λ on [0, 1]: when there are b sets,
+//! any particular group of k of them merges at rate
+//! λ_{b,k} = ∫ x^{k-2} (1-x)^{b-k} Λ(dx). The standard coalescent corresponds
+//! to Λ being the Dirac measure at zero.
+//!
+
+// Types
+use partitions::PartitionVec;
+use rand_distr::Exp;
+use rand::distributions::WeightedIndex;
+use crate::{Genealogy, Step};
+use crate::genealogy::apply_step;
+
+// Traits
+use crate::traits::LambdaMeasure;
+use markovian::traits::CMarkovChainTrait;
+use rand::distributions::Distribution;
+use rand::Rng;
+use core::fmt::Debug;
+use std::iter::FromIterator;
+
+/// Beta(2 - α, α) measure, for α in (0, 2). It arises from populations where
+/// the offspring distribution has a heavy tail of index α.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BetaMeasure {
+    alpha: f64,
+}
+
+impl BetaMeasure {
+    /// Creates a new BetaMeasure.
+    ///
+    /// # Panics
+    ///
+    /// If ``alpha`` is not in (0, 2).
+    pub fn new(alpha: f64) -> Self {
+        assert!(0.0 < alpha && alpha < 2.0, "The parameter alpha must be in (0, 2).");
+
+        BetaMeasure { alpha }
+    }
+
+    /// Parameter α of the measure.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+}
+
+impl LambdaMeasure for BetaMeasure {
+    fn merger_rate(&self, blocks: usize, merging: usize) -> f64 {
+        let alpha = self.alpha;
+        (ln_beta(merging as f64 - alpha, (blocks - merging) as f64 + alpha) - ln_beta(2.0 - alpha, alpha)).exp()
+    }
+
+    fn merger_rates(&self, blocks: usize) -> Vec<f64> {
+        let alpha = self.alpha;
+        (2..=blocks)
+            .map(|merging| {
+                (ln_binomial(blocks, merging)
+                    + ln_beta(merging as f64 - alpha, (blocks - merging) as f64 + alpha)
+                    - ln_beta(2.0 - alpha, alpha))
+                .exp()
+            })
+            .collect()
+    }
+}
+
+/// Dirac measure at ψ, for ψ in (0, 1]. At each event, every set takes part
+/// in the merger independently with probability ψ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiracMeasure {
+    psi: f64,
+}
+
+impl DiracMeasure {
+    /// Creates a new DiracMeasure.
+    ///
+    /// # Panics
+    ///
+    /// If ``psi`` is not in (0, 1].
+    pub fn new(psi: f64) -> Self {
+        assert!(0.0 < psi && psi <= 1.0, "The parameter psi must be in (0, 1].");
+
+        DiracMeasure { psi }
+    }
+
+    /// Atom ψ of the measure.
+    pub fn psi(&self) -> f64 {
+        self.psi
+    }
+}
+
+impl LambdaMeasure for DiracMeasure {
+    fn merger_rate(&self, blocks: usize, merging: usize) -> f64 {
+        self.psi.powi(merging as i32 - 2) * (1.0 - self.psi).powi((blocks - merging) as i32)
+    }
+
+    fn merger_rates(&self, blocks: usize) -> Vec<f64> {
+        if self.psi == 1.0 {
+            return (2..=blocks).map(|merging| (merging == blocks) as usize as f64).collect();
+        }
+        (2..=blocks)
+            .map(|merging| {
+                (ln_binomial(blocks, merging)
+                    + (merging as f64 - 2.0) * self.psi.ln()
+                    + (blocks - merging) as f64 * (1.0 - self.psi).ln())
+                .exp()
+            })
+            .collect()
+    }
+}
+
+/// Uniform measure on [0, 1], giving the Bolthausen-Sznitman coalescent.
+/// It is the Beta measure with α = 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BolthausenSznitman;
+
+impl LambdaMeasure for BolthausenSznitman {
+    fn merger_rate(&self, blocks: usize, merging: usize) -> f64 {
+        (ln_gamma((merging - 1) as f64) + ln_gamma((blocks - merging + 1) as f64) - ln_gamma(blocks as f64)).exp()
+    }
+
+    fn merger_rates(&self, blocks: usize) -> Vec<f64> {
+        (2..=blocks)
+            .map(|merging| blocks as f64 / (merging * (merging - 1)) as f64)
+            .collect()
+    }
+}
+
+/// Λ-coalescent process in the space of partitions of the set {1, 2, ..., n}.
+/// Starts with a finite partition of all singletons and it ends with a single set.
+///
+/// It has a random number generator associated, R, to be a random iterator.
+///
+/// # Examples
+///
+/// ```
+/// use coalescence::{BetaMeasure, LambdaCoalescent};
+///
+/// let group_size = 100;
+/// let rng = rand::thread_rng();
+/// let coalescent = LambdaCoalescent::new(group_size, BetaMeasure::new(1.5), rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LambdaCoalescent<L, R>
+where
+    L: LambdaMeasure,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    state: PartitionVec<()>,
+    measure: L,
+    time: f64,
+    rng: R,
+}
+
+impl<L, R> LambdaCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new LambdaCoalescent.
+    ///
+    /// # Examples
+    ///
+    /// With a user-supplied measure, here the Dirac measure at 1/2.
+    /// ```
+    /// let measure = |blocks: usize, _merging: usize| 0.5_f64.powi(blocks as i32 - 2);
+    /// let rng = rand::thread_rng();
+    /// let coalescent = coalescence::LambdaCoalescent::new(10, measure, rng);
+    /// ```
+    pub fn new(group_size: usize, measure: L, rng: R) -> Self {
+        let state: PartitionVec<()> =
+            PartitionVec::from_iter((0..group_size).map(|_| ()));
+
+        LambdaCoalescent { state, measure, time: 0.0, rng }
+    }
+
+    /// Current time of the process.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Measure Λ defining the process.
+    pub fn measure(&self) -> &L {
+        &self.measure
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Peeks a possible next step, i.e. a single merger of any number of sets,
+    /// chosen according to the stochastic process. This does not change the
+    /// state of the ``LambdaCoalescent``.
+    ///
+    /// # Panics
+    ///
+    /// If all merger rates are zero while there are several sets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::{DiracMeasure, LambdaCoalescent};
+    ///
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = LambdaCoalescent::new(10, DiracMeasure::new(1.0), rng);
+    ///
+    /// // All sets merge at once.
+    /// let (_time, step) = coalescent.peek_next_step().unwrap();
+    /// assert_eq!(step[0].len(), 10);
+    /// ```
+    pub fn peek_next_step(&mut self) -> Option<(f64, Step)> {
+        let current_partition_size = self.state.amount_of_sets();
+
+        if current_partition_size == 1 {
+            None
+        } else {
+            // Simulate time step
+
+            let rates = self.measure.merger_rates(current_partition_size);
+            let total_rate: f64 = rates.iter().sum();
+            assert!(total_rate > 0.0, "The merger rates must not be all zero.");
+            let time_step = Exp::new(total_rate).unwrap().sample(&mut self.rng);
+
+            // Choose between possible transitions
+
+            let merging = WeightedIndex::new(&rates).unwrap().sample(&mut self.rng) + 2;
+            let set_indexes = rand::seq::index::sample(&mut self.rng, current_partition_size, merging);
+
+            // Get values from these sets
+
+            let representatives: Vec<usize> = self.state
+                .all_sets()
+                .map(|mut set| set.next().unwrap().0)
+                .collect();
+            let value_indexes: Vec<usize> = set_indexes
+                .iter()
+                .map(|set_index| representatives[set_index])
+                .collect();
+
+            // Return
+
+            Some((time_step, vec![value_indexes]))
+        }
+    }
+
+    /// Changes to a next state of the ``LambdaCoalescent``, chosen
+    /// according to the stochastic process and returning the indexes of
+    /// elements that represent the sets of the partitions that were joint
+    /// to produce this next state.
+    pub fn next_step(&mut self) -> Option<(f64, Step)> {
+        match self.peek_next_step() {
+            Some((time_step, step)) => {
+                apply_step(&mut self.state, &step);
+                self.time += time_step;
+                Some((time_step, step))
+            },
+            None => None,
+        }
+    }
+
+    /// Sample a genealogy: from the initial partition of singletons until there
+    /// is only one set in the partition. Returns a ``Genealogy`` where
+    /// postprocess is possible.
+    ///
+    /// # Remarks
+    ///
+    /// No internal state changes, including the internal
+    /// random number generator. This is why this methods requires a rng.
+    ///
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Initialize a LambdaCoalescent
+
+        let group_size: usize = self.state.len();
+        let mut coalescent_process = LambdaCoalescent::new(group_size, self.measure.clone(), rng.clone());
+
+        // Generate transitions
+
+        let mut timed_steps = Vec::new();
+        while let Some((_, step)) = coalescent_process.next_step() {
+            timed_steps.push((coalescent_process.time(), step));
+        }
+
+        // Update rng
+
+        *rng = coalescent_process.rng.clone();
+
+        // Finish
+
+        Genealogy::from_timed_steps(group_size, timed_steps)
+    }
+}
+
+impl<L, R> CMarkovChainTrait<PartitionVec<()>> for LambdaCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Current state of the process.
+    fn state(&self) -> &PartitionVec<()> {
+        &self.state
+    }
+
+    /// Change the current state of the process.
+    fn set_state(&mut self, state: PartitionVec<()>) -> &mut Self {
+        self.state = state;
+        self
+    }
+}
+
+impl<L, R> Iterator for LambdaCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    type Item = (f64, PartitionVec<()>);
+
+    /// Changes the state of the ``LambdaCoalescent`` to a new state, chosen
+    /// according to the stochastic process.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_step() {
+            Some((time_step, _)) => Some((time_step, self.state.clone())),
+            None => None,
+        }
+    }
+}
+
+/// Natural logarithm of the gamma function, for positive arguments,
+/// by the Lanczos approximation.
+pub(crate) fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula
+        (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x)
+    } else {
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let series = COEFFICIENTS[1..]
+            .iter()
+            .enumerate()
+            .fold(COEFFICIENTS[0], |sum, (i, coefficient)| sum + coefficient / (x + i as f64 + 1.0));
+        0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+    }
+}
+
+/// Natural logarithm of the beta function.
+pub(crate) fn ln_beta(a: f64, b: f64) -> f64 {
+    ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b)
+}
+
+/// Natural logarithm of the binomial coefficient.
+pub(crate) fn ln_binomial(n: usize, k: usize) -> f64 {
+    ln_gamma(n as f64 + 1.0) - ln_gamma(k as f64 + 1.0) - ln_gamma((n - k) as f64 + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn consistent_rates() {
+        // λ_{b,k} = λ_{b+1,k} + λ_{b+1,k+1}
+        let measures: Vec<Box<dyn LambdaMeasure>> = vec![
+            Box::new(BetaMeasure::new(0.5)),
+            Box::new(BetaMeasure::new(1.7)),
+            Box::new(DiracMeasure::new(0.3)),
+            Box::new(BolthausenSznitman),
+        ];
+        for measure in measures {
+            for blocks in 2..10 {
+                for merging in 2..=blocks {
+                    let rate = measure.merger_rate(blocks, merging);
+                    let next_rates = measure.merger_rate(blocks + 1, merging) + measure.merger_rate(blocks + 1, merging + 1);
+                    assert!((rate - next_rates).abs() < 1e-10 * rate);
+                }
+                let total: f64 = measure.merger_rates(blocks).iter().sum();
+                let by_rate: f64 = (2..=blocks)
+                    .map(|merging| ln_binomial(blocks, merging).exp() * measure.merger_rate(blocks, merging))
+                    .sum();
+                assert!((total - by_rate).abs() < 1e-8 * total);
+            }
+        }
+    }
+
+    #[test]
+    fn bolthausen_sznitman_is_beta() {
+        let beta = BetaMeasure::new(1.0);
+        for (rate, other_rate) in beta.merger_rates(20).iter().zip(BolthausenSznitman.merger_rates(20)) {
+            assert!((rate - other_rate).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn multiple_mergers() {
+        let group_size = 50;
+        let rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let coalescent = LambdaCoalescent::new(group_size, BetaMeasure::new(1.0), rng);
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(8));
+
+        let merged: usize = genealogy.steps().iter().flatten().map(|merger| merger.len() - 1).sum();
+        assert_eq!(merged, group_size - 1);
+        assert!(genealogy.steps().len() < group_size - 1);
+    }
+}
@@ -1,10 +1,42 @@
 //! Coalescent process as described in [Coalescent Theory](https://en.wikipedia.org/wiki/Coalescent_theory)
 
+pub use arg::*;
 pub use coalescent::*;
+pub use demography::*;
 pub use genealogy::*;
+pub use lambda::*;
+pub use moran::*;
+pub use mutation::*;
+pub use ploidy::*;
+pub use seedbank::*;
+pub use selection::*;
+pub use smc::*;
+pub use species::*;
+pub use structured::*;
+pub use substitution::*;
+pub use sweep::*;
+pub use topology::*;
+pub use wright_fisher::*;
+pub use xi::*;
 
+pub mod arg;
 pub mod coalescent;
+pub mod demography;
 pub mod genealogy;
+pub mod lambda;
+pub mod moran;
+pub mod mutation;
+pub mod ploidy;
+pub mod seedbank;
+pub mod selection;
+pub mod smc;
+pub mod species;
+pub mod structured;
+pub mod substitution;
+pub mod sweep;
+pub mod topology;
+pub mod wright_fisher;
+pub mod xi;
 
 pub mod traits;
 
@@ -0,0 +1,432 @@
+//! Moran model.
+//!
+//! Forward in time simulation of a population of N haploid individuals with
+//! overlapping generations: each individual dies at rate one and is replaced
+//! by the offspring of another individual. Under selection, individuals
+//! carrying the favoured allele have fitness 1 + s and are chosen as parents
+//! proportionally to their fitness. Reproduction events are recorded, so
+//! that the genealogy of any group of the current population can be recovered.
+//!
+//! Without selection and measuring time in units of (N - 1) / 2 generations,
+//! genealogies are the ones of the standard ``Coalescent``.
+//!
+
+// Types
+use rand_distr::Exp;
+use std::collections::VecDeque;
+use crate::{Allele, Genealogy, Step};
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Reproduction event: the offspring of ``parent`` replaces ``dead``.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Replacement {
+    time: f64,
+    parent: usize,
+    dead: usize,
+}
+
+/// Moran population of haploid individuals, evolving forward in time.
+///
+/// Time is measured in generations, i.e. the mean lifetime of an individual.
+/// It has a random number generator associated, R, to choose the events.
+///
+/// # Remarks
+///
+/// Reproduction events are recorded until the common ancestor of the current
+/// population, which is usually found within 4N² events. Events older than
+/// it are discarded once that many are recorded; if the common ancestor is
+/// older, the number of events before the next attempt is doubled. Memory
+/// therefore grows as N², about 10 GB for N = 10⁴, so large populations
+/// are better simulated backwards in time, for example by ``Coalescent``.
+///
+/// # Examples
+///
+/// ```
+/// let population_size = 100;
+/// let rng = rand::thread_rng();
+/// let mut population = coalescence::Moran::new(population_size, rng);
+/// population.evolve(20.0 * population_size as f64);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = population.sample_genealogy(10, &mut rng).expect("The group has no common ancestor yet.");
+///
+/// // In coalescent units
+/// let depth = genealogy.depth() / ((population_size - 1) as f64 / 2.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Moran<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    population_size: usize,
+    selection_coefficient: f64,
+    alleles: Vec<Allele>,
+    by_allele: Vec<usize>, // individuals, those carrying the favoured allele first
+    positions: Vec<usize>, // position of each individual in by_allele
+    num_favoured: usize,
+    time: f64,
+    replacements: VecDeque<Replacement>, // from the oldest recorded one
+    discard_threshold: usize, // recorded events before trying to discard old ones
+    rng: R,
+}
+
+impl<R> Moran<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new neutral Moran population without history.
+    ///
+    /// # Panics
+    ///
+    /// If ``population_size`` is less than two.
+    pub fn new(population_size: usize, rng: R) -> Self {
+        assert!(population_size > 1, "The population must have at least two individuals.");
+
+        Moran {
+            population_size,
+            selection_coefficient: 0.0,
+            alleles: vec![Allele::Unfavoured; population_size],
+            by_allele: (0..population_size).collect(),
+            positions: (0..population_size).collect(),
+            num_favoured: 0,
+            time: 0.0,
+            replacements: VecDeque::new(),
+            discard_threshold: 4 * population_size * population_size,
+            rng,
+        }
+    }
+
+    /// Number of individuals in the population.
+    pub fn population_size(&self) -> usize {
+        self.population_size
+    }
+
+    /// Time simulated, in generations.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Selection coefficient s of the favoured allele.
+    pub fn selection_coefficient(&self) -> f64 {
+        self.selection_coefficient
+    }
+
+    /// Allele of each individual of the current population.
+    pub fn alleles(&self) -> &[Allele] {
+        &self.alleles
+    }
+
+    /// Change the selection coefficient of the favoured allele and the allele
+    /// of each individual of the current population.
+    ///
+    /// # Panics
+    ///
+    /// If ``selection_coefficient`` is less than -1 or there is not one allele
+    /// per individual.
+    ///
+    /// # Examples
+    ///
+    /// A favoured allele in a single individual.
+    /// ```
+    /// use coalescence::{Allele, Moran};
+    ///
+    /// let mut alleles = vec![Allele::Unfavoured; 50];
+    /// alleles[0] = Allele::Favoured;
+    /// let rng = rand::thread_rng();
+    /// let mut population = Moran::new(50, rng);
+    /// population.set_selection(0.5, alleles);
+    /// population.evolve(10.0);
+    /// ```
+    pub fn set_selection(&mut self, selection_coefficient: f64, alleles: Vec<Allele>) -> &mut Self {
+        assert!(selection_coefficient >= -1.0, "The selection coefficient must be at least -1.");
+        assert_eq!(alleles.len(), self.population_size, "There must be one allele per individual.");
+        self.selection_coefficient = selection_coefficient;
+        let carrying = |allele: Allele| (0..alleles.len()).filter(|&individual| alleles[individual] == allele).collect::<Vec<usize>>();
+        self.by_allele = [carrying(Allele::Favoured), carrying(Allele::Unfavoured)].concat();
+        for (position, &individual) in self.by_allele.iter().enumerate() {
+            self.positions[individual] = position;
+        }
+        self.num_favoured = alleles.iter().filter(|&&allele| allele == Allele::Favoured).count();
+        self.alleles = alleles;
+        self
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Produces the next reproduction event and returns the time elapsed.
+    pub fn next_event(&mut self) -> f64 {
+        let time_step = Exp::new(self.population_size as f64).unwrap().sample(&mut self.rng);
+        self.time += time_step;
+        self.reproduce();
+        time_step
+    }
+
+    /// Produces reproduction events for ``duration`` generations.
+    pub fn evolve(&mut self, duration: f64) -> &mut Self {
+        let end = self.time + duration;
+        loop {
+            let time_step = Exp::new(self.population_size as f64).unwrap().sample(&mut self.rng);
+            if self.time + time_step > end {
+                break;
+            }
+            self.time += time_step;
+            self.reproduce();
+        }
+        self.time = end;
+        self
+    }
+
+    /// Genealogy of the given individuals of the current population, with
+    /// time measured in generations. Individual i of the genealogy is
+    /// ``individuals[i]``.
+    ///
+    /// Returns ``None`` if the individuals have no common ancestor in the
+    /// recorded history.
+    ///
+    /// # Panics
+    ///
+    /// If some individual is not in the population or is repeated.
+    pub fn genealogy(&self, individuals: &[usize]) -> Option<Genealogy> {
+        // Representative of the lineage at each position, if any
+
+        let mut lineages: Vec<Option<usize>> = vec![None; self.population_size];
+        for (representative, &individual) in individuals.iter().enumerate() {
+            assert!(individual < self.population_size, "The individual is not in the population.");
+            assert!(lineages[individual].is_none(), "The individual is repeated.");
+            lineages[individual] = Some(representative);
+        }
+        let mut remaining = individuals.len();
+        let mut timed_steps: Vec<(f64, Step)> = Vec::new();
+
+        // Go backwards through the events
+
+        for replacement in self.replacements.iter().rev() {
+            if remaining <= 1 {
+                break;
+            }
+            if let Some(representative) = lineages[replacement.dead].take() {
+                match lineages[replacement.parent] {
+                    Some(other_representative) => {
+                        timed_steps.push((self.time - replacement.time, vec![vec![other_representative, representative]]));
+                        lineages[replacement.parent] = Some(other_representative.min(representative));
+                        remaining -= 1;
+                    },
+                    None => lineages[replacement.parent] = Some(representative),
+                }
+            }
+        }
+
+        match remaining <= 1 {
+            true => Some(Genealogy::from_timed_steps(individuals.len(), timed_steps)),
+            false => None,
+        }
+    }
+
+    /// Genealogy of ``sample_size`` different individuals of the current
+    /// population, chosen uniformly at random. See ``genealogy`` method.
+    ///
+    /// # Panics
+    ///
+    /// If ``sample_size`` is larger than the population size.
+    pub fn sample_genealogy<S>(&self, sample_size: usize, rng: &mut S) -> Option<Genealogy>
+    where
+        S: Rng,
+    {
+        let individuals = rand::seq::index::sample(rng, self.population_size, sample_size).into_vec();
+        self.genealogy(&individuals)
+    }
+
+    /// An individual dies and is replaced by the offspring of another one.
+    fn reproduce(&mut self) {
+        // Choose the individuals
+
+        let dead = self.rng.gen_range(0, self.population_size);
+        let uniform_parent = (dead + self.rng.gen_range(1, self.population_size)) % self.population_size;
+        let parent = if self.selection_coefficient == 0.0 {
+            uniform_parent
+        } else {
+            self.selected_parent(dead).unwrap_or(uniform_parent) // all other individuals have fitness zero
+        };
+
+        // Update
+
+        let allele = self.alleles[parent];
+        if allele != self.alleles[dead] {
+            // Move the dead individual to the other side of the favoured ones
+            let boundary = match allele {
+                Allele::Favoured => self.num_favoured,
+                Allele::Unfavoured => self.num_favoured - 1,
+            };
+            let other = self.by_allele[boundary];
+            self.by_allele.swap(self.positions[dead], boundary);
+            self.positions.swap(dead, other);
+            match allele {
+                Allele::Favoured => self.num_favoured += 1,
+                Allele::Unfavoured => self.num_favoured -= 1,
+            }
+        }
+        self.alleles[dead] = allele;
+        self.replacements.push_back(Replacement { time: self.time, parent, dead });
+        if self.replacements.len() > self.discard_threshold {
+            let default_threshold = 4 * self.population_size * self.population_size;
+            self.discard_threshold = match self.discard_old_events() {
+                true if self.replacements.len() < default_threshold => default_threshold,
+                true => 2 * self.replacements.len(),
+                false => 2 * self.discard_threshold,
+            };
+        }
+    }
+
+    /// Parent chosen proportionally to its fitness among the individuals
+    /// other than ``dead``: first the allele it carries, then uniformly among
+    /// those carrying it. Returns ``None`` if all of them have fitness zero.
+    fn selected_parent(&mut self, dead: usize) -> Option<usize> {
+        // Choose the allele
+
+        let dead_favoured = (self.alleles[dead] == Allele::Favoured) as usize;
+        let num_favoured = self.num_favoured - dead_favoured;
+        let num_unfavoured = self.population_size - self.num_favoured - (1 - dead_favoured);
+        let favoured_weight = (1.0 + self.selection_coefficient) * num_favoured as f64;
+        let total_weight = favoured_weight + num_unfavoured as f64;
+        if total_weight <= 0.0 {
+            return None;
+        }
+        let allele = match self.rng.gen::<f64>() * total_weight < favoured_weight {
+            true => Allele::Favoured,
+            false => Allele::Unfavoured,
+        };
+
+        // Choose the individual, skipping the dead one
+
+        let (start, size) = match allele {
+            Allele::Favoured => (0, num_favoured),
+            Allele::Unfavoured => (self.num_favoured, num_unfavoured),
+        };
+        let mut position = start + self.rng.gen_range(0, size);
+        if self.alleles[dead] == allele && position >= self.positions[dead] {
+            position += 1;
+        }
+        Some(self.by_allele[position])
+    }
+
+    /// Discards the events older than the common ancestor of the current
+    /// population. Returns whether the common ancestor was found.
+    fn discard_old_events(&mut self) -> bool {
+        let mut ancestors = vec![true; self.population_size];
+        let mut remaining = self.population_size;
+        for (events_back, replacement) in self.replacements.iter().rev().enumerate() {
+            if ancestors[replacement.dead] {
+                ancestors[replacement.dead] = false;
+                if ancestors[replacement.parent] {
+                    remaining -= 1;
+                } else {
+                    ancestors[replacement.parent] = true;
+                }
+            }
+            if remaining == 1 {
+                let discarded = self.replacements.len() - (events_back + 1);
+                self.replacements.drain(..discarded);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn pairs_coalesce_after_half_population_size_generations() {
+        let population_size = 30;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+
+        let samples = 300;
+        let mean_depth = (0..samples)
+            .map(|seed| {
+                let mut population = Moran::new(population_size, rand_pcg::Pcg32::seed_from_u64(seed));
+                population.evolve(10.0 * population_size as f64);
+                population.sample_genealogy(2, &mut rng).unwrap().depth()
+            })
+            .sum::<f64>() / samples as f64;
+
+        // Expected (N - 1) / 2 generations
+        let expected = (population_size - 1) as f64 / 2.0;
+        assert!((mean_depth / expected - 1.0).abs() < 0.15);
+    }
+
+    #[test]
+    fn favoured_allele_spreads() {
+        let population_size = 40;
+        let mut alleles = vec![Allele::Unfavoured; population_size];
+        alleles[..20].iter_mut().for_each(|allele| *allele = Allele::Favoured);
+        let mut population = Moran::new(population_size, rand_pcg::Pcg32::seed_from_u64(2));
+        population.set_selection(5.0, alleles);
+        population.evolve(5.0 * population_size as f64);
+
+        assert!(population.alleles().iter().all(|&allele| allele == Allele::Favoured));
+        let everyone: Vec<usize> = (0..population_size).collect();
+        let genealogy = population.genealogy(&everyone).unwrap();
+        assert_eq!(genealogy.steps().len(), population_size - 1);
+        assert!(population.replacements.len() <= 4 * population_size * population_size);
+    }
+
+    #[test]
+    fn discarding_backs_off_without_common_ancestor() {
+        let population_size = 10;
+        let mut population = Moran::new(population_size, rand_pcg::Pcg32::seed_from_u64(3));
+        let threshold = 4 * population_size * population_size;
+        population.replacements.extend(vec![Replacement { time: 0.0, parent: 0, dead: 0 }; threshold]);
+
+        population.next_event();
+        assert_eq!(population.replacements.len(), threshold + 1);
+        assert_eq!(population.discard_threshold, 2 * threshold);
+
+        population.evolve(20.0 * population_size as f64);
+        assert!(population.replacements.len() <= threshold);
+        assert_eq!(population.discard_threshold, threshold);
+    }
+
+    #[test]
+    fn parents_are_chosen_by_fitness() {
+        let population_size = 10;
+        let mut alleles = vec![Allele::Unfavoured; population_size];
+        alleles[..3].iter_mut().for_each(|allele| *allele = Allele::Favoured);
+        let mut population = Moran::new(population_size, rand_pcg::Pcg32::seed_from_u64(4));
+        population.set_selection(1.0, alleles);
+
+        // Favoured parents for a favoured dead individual: 2 * 2 / (2 * 2 + 7)
+        let samples = 20_000;
+        let parents: Vec<usize> = (0..samples).map(|_| population.selected_parent(0).unwrap()).collect();
+        let favoured = parents.iter().filter(|&&parent| population.alleles[parent] == Allele::Favoured).count();
+        assert!(!parents.contains(&0));
+        assert!((favoured as f64 / samples as f64 - 4.0 / 11.0).abs() < 0.02);
+
+        // Individuals stay sorted by allele as the population evolves
+        population.evolve(5.0);
+        let num_favoured = population.alleles().iter().filter(|&&allele| allele == Allele::Favoured).count();
+        assert_eq!(population.num_favoured, num_favoured);
+        for (position, &individual) in population.by_allele.iter().enumerate() {
+            assert_eq!(population.positions[individual], position);
+            assert_eq!(population.alleles[individual] == Allele::Favoured, position < num_favoured);
+        }
+    }
+
+    #[test]
+    fn lethal_allele_falls_back_to_uniform_parents() {
+        let mut population = Moran::new(3, rand_pcg::Pcg32::seed_from_u64(5));
+        population.set_selection(-1.0, vec![Allele::Unfavoured, Allele::Favoured, Allele::Favoured]);
+
+        assert_eq!(population.selected_parent(0), None);
+        population.evolve(10.0);
+    }
+}
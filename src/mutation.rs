@@ -0,0 +1,589 @@
+//! Mutations along a genealogy.
+//!
+//! Under the infinite-sites model, every mutation happens at a new site of
+//! the sequence, so that each segregating site is the result of exactly one
+//! mutation. Mutations fall on each branch of the genealogy as a Poisson
+//! process of rate θ / 2, where θ is the population-scaled mutation rate of
+//! the whole sequence, and all individuals descending from the branch carry
+//! the derived allele.
+//!
+//! Under the infinite-alleles model, every mutation creates a new allele, so
+//! that two individuals carry the same allele if and only if no mutation
+//! happened on the path between them. The resulting allelic partitions of a
+//! coalescent follow the Ewens sampling formula.
+//!
+//! Under the stepwise mutation model, used for microsatellites, every mutation
+//! changes the number of repeats of the allele by one unit up or down. In the
+//! generalized version, the size of the change follows a geometric distribution.
+//!
+
+// Types
+use rand_distr::Poisson;
+use crate::{Branch, Genealogy};
+use crate::genealogy::fold;
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Infinite-sites mutation model.
+///
+/// # Examples
+///
+/// ```
+/// use coalescence::{Coalescent, InfiniteSites};
+///
+/// let rng = rand::thread_rng();
+/// let coalescent = Coalescent::new(10, rng);
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// let haplotypes = InfiniteSites::new(5.0).sample_haplotypes(&genealogy, &mut rng);
+/// assert_eq!(haplotypes.matrix().len(), 10);
+/// for (site, branch) in haplotypes.branches().iter().enumerate() {
+///     let carriers: Vec<usize> = (0..10).filter(|&i| haplotypes.matrix()[i][site]).collect();
+///     assert_eq!(carriers, branch.descendants());
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InfiniteSites {
+    mutation_rate: f64,
+}
+
+impl InfiniteSites {
+    /// Creates a new InfiniteSites model with population-scaled mutation rate θ.
+    ///
+    /// # Panics
+    ///
+    /// If ``mutation_rate`` is negative.
+    pub fn new(mutation_rate: f64) -> Self {
+        assert!(mutation_rate >= 0.0, "The mutation rate must be non-negative.");
+
+        InfiniteSites { mutation_rate }
+    }
+
+    /// Population-scaled mutation rate θ of the sequence.
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    /// Throws mutations onto every branch of ``genealogy`` and returns the
+    /// resulting haplotypes of its individuals.
+    pub fn sample_haplotypes<S>(&self, genealogy: &Genealogy, rng: &mut S) -> Haplotypes
+    where
+        S: Rng,
+    {
+        // Mutations of each branch
+
+        let mut mutations: Vec<(f64, f64, Branch)> = Vec::new(); // position, time and branch
+        for branch in genealogy.branches() {
+            let mean = self.mutation_rate / 2.0 * branch.length();
+            if mean <= 0.0 {
+                continue;
+            }
+            let amount: u64 = Poisson::new(mean).unwrap().sample(rng);
+            for _ in 0..amount {
+                let position = rng.gen::<f64>();
+                let time = branch.start_time() + rng.gen::<f64>() * branch.length();
+                mutations.push((position, time, branch.clone()));
+            }
+        }
+        mutations.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        // Haplotype matrix
+
+        let mut matrix = vec![vec![false; mutations.len()]; genealogy.group_size()];
+        for (site, (_, _, branch)) in mutations.iter().enumerate() {
+            for &individual in branch.descendants() {
+                matrix[individual][site] = true;
+            }
+        }
+
+        // Finish
+
+        let positions = mutations.iter().map(|mutation| mutation.0).collect();
+        let times = mutations.iter().map(|mutation| mutation.1).collect();
+        let branches = mutations.into_iter().map(|mutation| mutation.2).collect();
+        Haplotypes { matrix, positions, times, branches }
+    }
+}
+
+/// Infinite-alleles mutation model.
+///
+/// # Examples
+///
+/// ```
+/// use coalescence::{Coalescent, InfiniteAlleles};
+///
+/// let rng = rand::thread_rng();
+/// let coalescent = Coalescent::new(10, rng);
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// let model = InfiniteAlleles::new(2.0);
+/// let alleles = model.sample_alleles(&genealogy, &mut rng);
+/// let probability = model.ewens_probability(&alleles.allele_counts());
+/// assert!(probability > 0.0 && probability <= 1.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InfiniteAlleles {
+    mutation_rate: f64,
+}
+
+impl InfiniteAlleles {
+    /// Creates a new InfiniteAlleles model with population-scaled mutation rate θ.
+    ///
+    /// # Panics
+    ///
+    /// If ``mutation_rate`` is negative.
+    pub fn new(mutation_rate: f64) -> Self {
+        assert!(mutation_rate >= 0.0, "The mutation rate must be non-negative.");
+
+        InfiniteAlleles { mutation_rate }
+    }
+
+    /// Population-scaled mutation rate θ of the locus.
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    /// Throws mutations onto every branch of ``genealogy`` and returns the
+    /// resulting alleles of its individuals.
+    ///
+    /// # Remarks
+    ///
+    /// Only the most recent mutation above each individual matters, so it
+    /// suffices to know which branches have at least one mutation.
+    pub fn sample_alleles<S>(&self, genealogy: &Genealogy, rng: &mut S) -> Alleles
+    where
+        S: Rng,
+    {
+        // Most recent mutated branch above each individual
+
+        let mut mutated_branches: Vec<Option<usize>> = vec![None; genealogy.group_size()];
+        for (index, branch) in genealogy.branches().iter().enumerate() {
+            let probability = -(-self.mutation_rate / 2.0 * branch.length()).exp_m1();
+            if rng.gen_bool(probability.min(1.0)) {
+                for &individual in branch.descendants() {
+                    // Branches are sorted by time, so the first one found is the most recent
+                    mutated_branches[individual].get_or_insert(index);
+                }
+            }
+        }
+
+        // Label alleles by first appearance
+
+        let mut labels: Vec<Option<usize>> = Vec::new();
+        let mut alleles = Vec::with_capacity(mutated_branches.len());
+        for mutated_branch in mutated_branches {
+            let label = match labels.iter().position(|&label| label == mutated_branch) {
+                Some(allele) => allele,
+                None => {
+                    labels.push(mutated_branch);
+                    labels.len() - 1
+                },
+            };
+            alleles.push(label);
+        }
+
+        Alleles { alleles, num_alleles: labels.len() }
+    }
+
+    /// Probability of the allele counts ``allele_counts`` in a sample of a
+    /// standard coalescent, given by the Ewens sampling formula. See
+    /// ``Alleles::allele_counts`` method.
+    ///
+    /// # Examples
+    ///
+    /// Two individuals share their allele with probability 1 / (1 + θ).
+    /// ```
+    /// let model = coalescence::InfiniteAlleles::new(3.0);
+    /// assert!((model.ewens_probability(&[0, 1]) - 0.25).abs() < 1e-10);
+    /// ```
+    pub fn ewens_probability(&self, allele_counts: &[usize]) -> f64 {
+        let sample_size: usize = allele_counts.iter().enumerate().map(|(j, a)| (j + 1) * a).sum();
+        let theta = self.mutation_rate;
+
+        // Without mutations, all individuals share the allele
+
+        if theta == 0.0 {
+            let num_alleles: usize = allele_counts.iter().sum();
+            return match num_alleles <= 1 {
+                true => 1.0,
+                false => 0.0,
+            };
+        }
+
+        // Logarithm of n! / (θ (θ + 1) ... (θ + n - 1))
+
+        let mut log_probability: f64 = (1..=sample_size)
+            .map(|i| (i as f64).ln() - (theta + (i - 1) as f64).ln())
+            .sum();
+
+        // Logarithm of the product of (θ / j)^a_j / a_j!
+
+        for (j, &count) in allele_counts.iter().enumerate() {
+            let size = (j + 1) as f64;
+            log_probability += count as f64 * (theta / size).ln();
+            log_probability -= (1..=count).map(|i| (i as f64).ln()).sum::<f64>();
+        }
+
+        log_probability.exp()
+    }
+}
+
+/// Alleles of a group of individuals at a locus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alleles {
+    alleles: Vec<usize>, // labelled by first appearance
+    num_alleles: usize,
+}
+
+impl Alleles {
+    /// Allele of each individual, labelled from zero in order of first appearance.
+    pub fn alleles(&self) -> &[usize] {
+        &self.alleles
+    }
+
+    /// Number of different alleles in the group.
+    pub fn num_alleles(&self) -> usize {
+        self.num_alleles
+    }
+
+    /// Allelic partition of the group: the individuals carrying each allele.
+    pub fn partition(&self) -> Vec<Vec<usize>> {
+        let mut partition = vec![Vec::new(); self.num_alleles];
+        for (individual, &allele) in self.alleles.iter().enumerate() {
+            partition[allele].push(individual);
+        }
+        partition
+    }
+
+    /// Allele counts (a_1, ..., a_n): the number of alleles carried by exactly
+    /// j individuals is at index j - 1.
+    pub fn allele_counts(&self) -> Vec<usize> {
+        let mut allele_counts = vec![0; self.alleles.len()];
+        for block in self.partition() {
+            allele_counts[block.len() - 1] += 1;
+        }
+        allele_counts
+    }
+}
+
+/// Stepwise mutation model of microsatellites.
+///
+/// # Examples
+///
+/// ```
+/// use coalescence::{Coalescent, StepwiseMutation};
+///
+/// let rng = rand::thread_rng();
+/// let coalescent = Coalescent::new(10, rng);
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// let microsatellite = StepwiseMutation::new(3.0).sample_repeats(&genealogy, &mut rng);
+/// assert_eq!(microsatellite.repeats().len(), 10);
+/// assert!(microsatellite.variance() >= 0.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepwiseMutation {
+    mutation_rate: f64,
+    step_probability: f64,
+}
+
+impl StepwiseMutation {
+    /// Creates a new StepwiseMutation model with population-scaled mutation rate θ,
+    /// where every mutation changes the number of repeats by one.
+    ///
+    /// # Panics
+    ///
+    /// If ``mutation_rate`` is negative.
+    pub fn new(mutation_rate: f64) -> Self {
+        StepwiseMutation::generalized(mutation_rate, 1.0)
+    }
+
+    /// Creates a new generalized StepwiseMutation model with population-scaled
+    /// mutation rate θ, where every mutation changes the number of repeats by
+    /// k units with probability (1 - p)^(k - 1) p.
+    ///
+    /// # Panics
+    ///
+    /// If ``mutation_rate`` is negative or ``step_probability`` is not in (0, 1].
+    pub fn generalized(mutation_rate: f64, step_probability: f64) -> Self {
+        assert!(mutation_rate >= 0.0, "The mutation rate must be non-negative.");
+        assert!(step_probability > 0.0 && step_probability <= 1.0, "The step probability must be in (0, 1].");
+
+        StepwiseMutation { mutation_rate, step_probability }
+    }
+
+    /// Population-scaled mutation rate θ of the locus.
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    /// Parameter p of the geometric distribution of the size of mutations.
+    pub fn step_probability(&self) -> f64 {
+        self.step_probability
+    }
+
+    /// Throws mutations onto every branch of ``genealogy`` and returns the
+    /// resulting number of repeats of its individuals, relative to the
+    /// common ancestor.
+    pub fn sample_repeats<S>(&self, genealogy: &Genealogy, rng: &mut S) -> Microsatellite
+    where
+        S: Rng,
+    {
+        let mut repeats = vec![0; genealogy.group_size()];
+        for branch in genealogy.branches() {
+            // Total change along the branch
+
+            let mean = self.mutation_rate / 2.0 * branch.length();
+            if mean <= 0.0 {
+                continue;
+            }
+            let amount: u64 = Poisson::new(mean).unwrap().sample(rng);
+            let change: i64 = (0..amount)
+                .map(|_| {
+                    let size = match self.step_probability < 1.0 {
+                        true => 1 + ((1.0 - rng.gen::<f64>()).ln() / (-self.step_probability).ln_1p()).floor() as i64,
+                        false => 1,
+                    };
+                    if rng.gen_bool(0.5) { size } else { -size }
+                })
+                .sum();
+
+            // Update descendants
+
+            for &individual in branch.descendants() {
+                repeats[individual] += change;
+            }
+        }
+
+        Microsatellite { repeats }
+    }
+}
+
+/// Number of repeats of a microsatellite in a group of individuals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Microsatellite {
+    repeats: Vec<i64>, // relative to the common ancestor
+}
+
+impl Microsatellite {
+    /// Number of repeats of each individual, relative to the common ancestor
+    /// of the group.
+    pub fn repeats(&self) -> &[i64] {
+        &self.repeats
+    }
+
+    /// Sample variance of the number of repeats, with denominator n - 1.
+    ///
+    /// # Remarks
+    ///
+    /// Under the stepwise mutation model, its expected value is θ / 2. If
+    /// the group has a single individual, the result is NaN.
+    pub fn variance(&self) -> f64 {
+        let group_size = self.repeats.len() as f64;
+        let mean = self.repeats.iter().sum::<i64>() as f64 / group_size;
+        self.repeats.iter().map(|&repeat| (repeat as f64 - mean).powi(2)).sum::<f64>() / (group_size - 1.0)
+    }
+}
+
+/// Haplotypes of a group of individuals at the segregating sites of a
+/// sequence, sorted by position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Haplotypes {
+    matrix: Vec<Vec<bool>>, // individuals x sites, true for the derived allele
+    positions: Vec<f64>,
+    times: Vec<f64>,
+    branches: Vec<Branch>,
+}
+
+impl Haplotypes {
+    /// Haplotype matrix: for each individual and segregating site, whether
+    /// the individual carries the derived allele.
+    pub fn matrix(&self) -> &[Vec<bool>] {
+        &self.matrix
+    }
+
+    /// Number of segregating sites.
+    pub fn num_segregating_sites(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Position of each segregating site, in [0, 1).
+    pub fn positions(&self) -> &[f64] {
+        &self.positions
+    }
+
+    /// Time at which the mutation of each segregating site happened.
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+
+    /// Branch of the genealogy on which the mutation of each segregating site happened.
+    pub fn branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    /// Site frequency spectrum: number of segregating sites where exactly i
+    /// individuals carry the derived allele, at index i - 1 for i = 1, ..., n - 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::{Coalescent, InfiniteSites};
+    ///
+    /// let rng = rand::thread_rng();
+    /// let coalescent = Coalescent::new(10, rng);
+    /// let mut rng = rand::thread_rng();
+    /// let genealogy = coalescent.sample_genealogy(&mut rng);
+    ///
+    /// let haplotypes = InfiniteSites::new(5.0).sample_haplotypes(&genealogy, &mut rng);
+    /// let spectrum = haplotypes.site_frequency_spectrum();
+    /// assert_eq!(spectrum.iter().sum::<usize>(), haplotypes.num_segregating_sites());
+    /// ```
+    pub fn site_frequency_spectrum(&self) -> Vec<usize> {
+        let group_size = self.matrix.len();
+        let mut spectrum = vec![0; group_size.saturating_sub(1)];
+        for site in 0..self.num_segregating_sites() {
+            let carriers = self.matrix.iter().filter(|haplotype| haplotype[site]).count();
+            if carriers > 0 && carriers < group_size {
+                spectrum[carriers - 1] += 1;
+            }
+        }
+        spectrum
+    }
+
+    /// Folded site frequency spectrum: number of segregating sites where the
+    /// minor allele is carried by exactly i individuals, at index i - 1 for
+    /// i = 1, ..., n / 2. It does not need to know which allele is derived.
+    pub fn folded_site_frequency_spectrum(&self) -> Vec<usize> {
+        fold(&self.site_frequency_spectrum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn watterson_expectation() {
+        let group_size = 5;
+        let mutation_rate = 4.0;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(0);
+
+        let samples = 2000;
+        let mean_segregating_sites = (0..samples)
+            .map(|seed| {
+                let coalescent = crate::Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(seed));
+                let genealogy = coalescent.sample_genealogy(&mut rng);
+                InfiniteSites::new(mutation_rate).sample_haplotypes(&genealogy, &mut rng).num_segregating_sites()
+            })
+            .sum::<usize>() as f64 / samples as f64;
+
+        // Expected θ (1 + 1/2 + ... + 1/(n - 1))
+        let expected = mutation_rate * (1..group_size).map(|i| 1.0 / i as f64).sum::<f64>();
+        assert!((mean_segregating_sites / expected - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn site_frequency_spectrum() {
+        let group_size = 4;
+        let mutation_rate = 4.0;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(5);
+
+        let samples = 3000;
+        let mut observed = vec![0.0; group_size - 1];
+        let mut expected = vec![0.0; group_size - 1];
+        for seed in 0..samples {
+            let coalescent = crate::Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(seed));
+            let genealogy = coalescent.sample_genealogy(&mut rng);
+            let haplotypes = InfiniteSites::new(mutation_rate).sample_haplotypes(&genealogy, &mut rng);
+            for (i, count) in haplotypes.site_frequency_spectrum().into_iter().enumerate() {
+                observed[i] += count as f64 / samples as f64;
+            }
+            for (i, length) in genealogy.length_spectrum().into_iter().enumerate() {
+                expected[i] += mutation_rate / 2.0 * length / samples as f64;
+            }
+            let folded = haplotypes.folded_site_frequency_spectrum();
+            assert_eq!(folded.iter().sum::<usize>(), haplotypes.num_segregating_sites());
+        }
+
+        // Expected θ / i under the standard coalescent
+        for i in 0..(group_size - 1) {
+            let theoretical = mutation_rate / (i + 1) as f64;
+            assert!((observed[i] / theoretical - 1.0).abs() < 0.1);
+            assert!((expected[i] / theoretical - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn ewens_sampling_formula() {
+        let group_size = 3;
+        let model = InfiniteAlleles::new(1.5);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+
+        // Frequency of each allele count vector
+        let samples = 5000;
+        let mut frequencies = std::collections::HashMap::new();
+        for seed in 0..samples {
+            let coalescent = crate::Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(seed));
+            let genealogy = coalescent.sample_genealogy(&mut rng);
+            let alleles = model.sample_alleles(&genealogy, &mut rng);
+            assert_eq!(alleles.partition().iter().map(|block| block.len()).sum::<usize>(), group_size);
+            *frequencies.entry(alleles.allele_counts()).or_insert(0) += 1;
+        }
+
+        let configurations = [vec![3, 0, 0], vec![1, 1, 0], vec![0, 0, 1]];
+        let total: f64 = configurations.iter().map(|counts| model.ewens_probability(counts)).sum();
+        assert!((total - 1.0).abs() < 1e-10);
+        let without_mutations = InfiniteAlleles::new(0.0);
+        assert_eq!(without_mutations.ewens_probability(&[0, 0, 1]), 1.0);
+        assert_eq!(without_mutations.ewens_probability(&[1, 1, 0]), 0.0);
+        for counts in configurations.iter() {
+            let frequency = *frequencies.get(counts).unwrap_or(&0) as f64 / samples as f64;
+            assert!((frequency - model.ewens_probability(counts)).abs() < 0.03);
+        }
+    }
+
+    #[test]
+    fn stepwise_variance() {
+        let group_size = 6;
+        let mutation_rate = 2.0;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(4);
+
+        let samples = 4000;
+        let (mut variance, mut generalized_variance) = (0.0, 0.0);
+        for seed in 0..samples {
+            let coalescent = crate::Coalescent::new(group_size, rand_pcg::Pcg32::seed_from_u64(seed));
+            let genealogy = coalescent.sample_genealogy(&mut rng);
+            variance += StepwiseMutation::new(mutation_rate).sample_repeats(&genealogy, &mut rng).variance();
+            generalized_variance += StepwiseMutation::generalized(mutation_rate, 0.5).sample_repeats(&genealogy, &mut rng).variance();
+        }
+
+        // Expected θ E[k^2] / 2, with E[k^2] = (2 - p) / p^2
+        assert!((variance / samples as f64 / (mutation_rate / 2.0) - 1.0).abs() < 0.1);
+        assert!((generalized_variance / samples as f64 / (mutation_rate * 6.0 / 2.0) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn sites_sorted_and_inside_branches() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+        let coalescent = crate::Coalescent::new(8, rand_pcg::Pcg32::seed_from_u64(2));
+        let genealogy = coalescent.sample_genealogy(&mut rng);
+        let haplotypes = InfiniteSites::new(20.0).sample_haplotypes(&genealogy, &mut rng);
+
+        assert!(haplotypes.num_segregating_sites() > 0);
+        assert!(haplotypes.positions().windows(2).all(|pair| pair[0] <= pair[1]));
+        for (time, branch) in haplotypes.times().iter().zip(haplotypes.branches()) {
+            assert!(branch.start_time() <= *time && *time <= branch.end_time());
+        }
+        assert!(haplotypes.matrix().iter().all(|haplotype| haplotype.len() == haplotypes.num_segregating_sites()));
+
+        let no_mutations = InfiniteSites::new(0.0).sample_haplotypes(&genealogy, &mut rng);
+        assert_eq!(no_mutations.num_segregating_sites(), 0);
+    }
+}
@@ -0,0 +1,111 @@
+//! Ploidy of a population.
+//!
+//! The number of copies of a locus carried by each individual, and the way
+//! they are inherited, change the rate at which lineages coalesce. Measuring
+//! time in units of N generations, where N is the number of individuals of the
+//! population, pairs of lineages coalesce at a rate that depends on the ploidy:
+//! - Haploid: rate 1.
+//! - Diploid autosomal locus: rate (1 + F) / 2, where F = s / (2 - s) is the
+//!   inbreeding coefficient under a selfing rate s. Besides, the two copies
+//!   carried by a sampled individual coalesce at once with probability F.
+//! - X-linked locus, with N_m males and N_f females: rate
+//!   N (4 N_m + 2 N_f) / (18 N_m N_f), i.e. the effective size is
+//!   9 N_m N_f / (4 N_m + 2 N_f) diploid individuals.
+//! - Haplodiploid species, with haploid males and diploid females: the same
+//!   as an X-linked locus.
+//!
+
+/// Ploidy of a population and inheritance of the locus.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Ploidy {
+    /// Each individual carries one copy.
+    #[default]
+    Haploid,
+    /// Each individual carries two copies, one from each parent. Copies 2i
+    /// and 2i + 1 of the group are carried by the same individual.
+    Diploid {
+        /// Probability that an individual is produced by selfing.
+        selfing_rate: f64,
+    },
+    /// Locus on the X chromosome: females carry two copies and males one.
+    XLinked {
+        /// Relative number of males.
+        males: f64,
+        /// Relative number of females.
+        females: f64,
+    },
+    /// Haploid males and diploid females, as in bees and ants.
+    Haplodiploid {
+        /// Relative number of males.
+        males: f64,
+        /// Relative number of females.
+        females: f64,
+    },
+}
+
+impl Ploidy {
+    /// Diploid population without selfing.
+    pub fn diploid() -> Self {
+        Ploidy::Diploid { selfing_rate: 0.0 }
+    }
+
+    /// Rate at which a pair of lineages coalesces, relative to a haploid
+    /// population of the same number of individuals.
+    ///
+    /// # Panics
+    ///
+    /// If the selfing rate is not a probability or the number of males or
+    /// females is not positive.
+    ///
+    /// # Examples
+    ///
+    /// Genealogies of the X chromosome are shorter than autosomal ones.
+    /// ```
+    /// use coalescence::Ploidy;
+    ///
+    /// let autosomal = Ploidy::diploid().coalescence_rate();
+    /// let x_linked = Ploidy::XLinked { males: 1.0, females: 1.0 }.coalescence_rate();
+    ///
+    /// assert!((x_linked / autosomal - 4.0 / 3.0).abs() < 1e-10);
+    /// ```
+    pub fn coalescence_rate(&self) -> f64 {
+        match *self {
+            Ploidy::Haploid => 1.0,
+            Ploidy::Diploid { .. } => (1.0 + self.inbreeding_coefficient()) / 2.0,
+            Ploidy::XLinked { males, females } | Ploidy::Haplodiploid { males, females } => {
+                assert!(males > 0.0 && females > 0.0, "There must be males and females.");
+                (males + females) * (4.0 * males + 2.0 * females) / (18.0 * males * females)
+            },
+        }
+    }
+
+    /// Probability that the two copies carried by an individual descend from
+    /// the same copy of a recent ancestor, due to selfing.
+    pub fn inbreeding_coefficient(&self) -> f64 {
+        match *self {
+            Ploidy::Diploid { selfing_rate } => {
+                assert!((0.0..=1.0).contains(&selfing_rate), "The selfing rate must be a probability.");
+                selfing_rate / (2.0 - selfing_rate)
+            },
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalescence_rates() {
+        assert_eq!(Ploidy::Haploid.coalescence_rate(), 1.0);
+        assert_eq!(Ploidy::diploid().coalescence_rate(), 0.5);
+        assert_eq!(Ploidy::Diploid { selfing_rate: 1.0 }.coalescence_rate(), 1.0);
+
+        // Equal sexes: effective size is 3/4 of the autosomal one.
+        let x_linked = Ploidy::XLinked { males: 0.5, females: 0.5 };
+        assert!((x_linked.coalescence_rate() - 2.0 / 3.0).abs() < 1e-10);
+        let haplodiploid = Ploidy::Haplodiploid { males: 0.5, females: 0.5 };
+        assert_eq!(haplodiploid.coalescence_rate(), x_linked.coalescence_rate());
+    }
+}
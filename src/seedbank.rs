@@ -0,0 +1,323 @@
+//! Seed-bank coalescent process.
+//!
+//! Coalescent of a population with a seed bank, as in Blath et al. (2016),
+//! where lineages are either active or dormant. Going backwards in time,
+//! each active lineage becomes dormant at rate c, each dormant lineage becomes
+//! active at rate c K, and each pair of active lineages coalesces at rate one.
+//! Dormant lineages do not coalesce, so that genealogies are much deeper than
+//! those of the standard coalescent.
+//!
+
+// Types
+use partitions::PartitionVec;
+use rand_distr::Exp;
+use rand::distributions::WeightedIndex;
+use crate::{Genealogy, Step};
+use crate::genealogy::apply_step;
+
+// Traits
+use markovian::traits::CMarkovChainTrait;
+use rand::distributions::Distribution;
+use rand::Rng;
+use core::fmt::Debug;
+use std::iter::FromIterator;
+
+/// State of a seed-bank coalescent: a partition of the group and whether the
+/// lineage carrying each individual is dormant.
+pub type SeedBankState = (PartitionVec<()>, Vec<bool>);
+
+/// Transition of the seed-bank coalescent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeedBankEvent {
+    /// Sets of the partition joint, all of them active.
+    Coalescence(Step),
+    /// Active lineage, given by one value index of its set, becoming dormant.
+    Dormancy(usize),
+    /// Dormant lineage, given by one value index of its set, becoming active.
+    Resuscitation(usize),
+}
+
+/// Seed-bank coalescent process, where lineages switch between an active and
+/// a dormant state and only active lineages coalesce.
+///
+/// Individuals are numbered so that the first ones are sampled active and the
+/// rest are sampled dormant.
+///
+/// # Examples
+///
+/// ```
+/// let rng = rand::thread_rng();
+/// let coalescent = coalescence::SeedBankCoalescent::new(10, 5, 1.0, 0.5, rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+/// let depth = genealogy.depth();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeedBankCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    state: SeedBankState,
+    active_sample_size: usize,
+    dormancy_rate: f64,
+    resuscitation_rate: f64,
+    time: f64,
+    rng: R,
+}
+
+impl<R> SeedBankCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new SeedBankCoalescent of ``active_sample_size`` active and
+    /// ``dormant_sample_size`` dormant individuals. In terms of Blath et al.,
+    /// ``dormancy_rate`` is c and ``resuscitation_rate`` is c K.
+    ///
+    /// # Panics
+    ///
+    /// If ``dormancy_rate`` is negative or ``resuscitation_rate`` is not positive.
+    pub fn new(active_sample_size: usize, dormant_sample_size: usize, dormancy_rate: f64, resuscitation_rate: f64, rng: R) -> Self {
+        assert!(dormancy_rate >= 0.0, "The dormancy rate must be non-negative.");
+        assert!(resuscitation_rate > 0.0, "The resuscitation rate must be positive.");
+
+        let group_size = active_sample_size + dormant_sample_size;
+        let partition: PartitionVec<()> = PartitionVec::from_iter((0..group_size).map(|_| ()));
+        let dormant: Vec<bool> = (0..group_size).map(|i| i >= active_sample_size).collect();
+
+        SeedBankCoalescent {
+            state: (partition, dormant),
+            active_sample_size,
+            dormancy_rate,
+            resuscitation_rate,
+            time: 0.0,
+            rng,
+        }
+    }
+
+    /// Current time of the process, measured backwards from the present.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Number of individuals sampled active.
+    pub fn active_sample_size(&self) -> usize {
+        self.active_sample_size
+    }
+
+    /// Rate at which each active lineage becomes dormant.
+    pub fn dormancy_rate(&self) -> f64 {
+        self.dormancy_rate
+    }
+
+    /// Rate at which each dormant lineage becomes active.
+    pub fn resuscitation_rate(&self) -> f64 {
+        self.resuscitation_rate
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Peeks a possible next event, chosen according to the stochastic process.
+    /// This does not change the state of the ``SeedBankCoalescent``.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::SeedBankEvent;
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = coalescence::SeedBankCoalescent::new(1, 1, 1.0, 1.0, rng);
+    ///
+    /// // A dormant lineage can not coalesce.
+    /// match coalescent.peek_next_step().unwrap() {
+    ///     (_time, SeedBankEvent::Coalescence(_)) => unreachable!(),
+    ///     (_time, _switch) => (),
+    /// }
+    /// ```
+    pub fn peek_next_step(&mut self) -> Option<(f64, SeedBankEvent)> {
+        let (partition, dormant) = &self.state;
+        let lineages: Vec<usize> = partition
+            .all_sets()
+            .map(|mut set| set.next().unwrap().0)
+            .collect();
+
+        if lineages.len() == 1 {
+            None
+        } else {
+            // Compute rates
+
+            let (dormant_lineages, active_lineages): (Vec<usize>, Vec<usize>) = lineages
+                .into_iter()
+                .partition(|&lineage| dormant[lineage]);
+            let k = active_lineages.len();
+            let rates = [
+                (k * k.saturating_sub(1) / 2) as f64,
+                k as f64 * self.dormancy_rate,
+                dormant_lineages.len() as f64 * self.resuscitation_rate,
+            ];
+            let total_rate: f64 = rates.iter().sum();
+
+            // Simulate time step
+
+            let time_step = Exp::new(total_rate).unwrap().sample(&mut self.rng);
+
+            // Choose between possible transitions
+
+            let event = match WeightedIndex::new(rates).unwrap().sample(&mut self.rng) {
+                0 => {
+                    let pair = rand::seq::index::sample(&mut self.rng, k, 2);
+                    SeedBankEvent::Coalescence(vec![vec![active_lineages[pair.index(0)], active_lineages[pair.index(1)]]])
+                },
+                1 => SeedBankEvent::Dormancy(active_lineages[self.rng.gen_range(0, k)]),
+                _ => SeedBankEvent::Resuscitation(dormant_lineages[self.rng.gen_range(0, dormant_lineages.len())]),
+            };
+
+            // Return
+
+            Some((time_step, event))
+        }
+    }
+
+    /// Changes to a next state of the ``SeedBankCoalescent``, chosen
+    /// according to the stochastic process and returning the event that
+    /// produced this next state.
+    pub fn next_step(&mut self) -> Option<(f64, SeedBankEvent)> {
+        match self.peek_next_step() {
+            Some((time_step, event)) => {
+                apply_event(&mut self.state, &event);
+                self.time += time_step;
+                Some((time_step, event))
+            },
+            None => None,
+        }
+    }
+
+    /// Sample a genealogy: from the initial state until there is only one set
+    /// in the partition. Returns a ``Genealogy`` where postprocess is possible.
+    /// Switches between active and dormant states are not recorded.
+    ///
+    /// # Remarks
+    ///
+    /// No internal state changes, including the internal
+    /// random number generator. This is why this methods requires a rng.
+    ///
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Initialize a SeedBankCoalescent
+
+        let group_size = self.state.0.len();
+        let mut coalescent_process = SeedBankCoalescent::new(
+            self.active_sample_size,
+            group_size - self.active_sample_size,
+            self.dormancy_rate,
+            self.resuscitation_rate,
+            rng.clone(),
+        );
+
+        // Generate transitions
+
+        let mut timed_steps = Vec::new();
+        while let Some((_, event)) = coalescent_process.next_step() {
+            if let SeedBankEvent::Coalescence(step) = event {
+                timed_steps.push((coalescent_process.time(), step));
+            }
+        }
+
+        // Update rng
+
+        *rng = coalescent_process.rng.clone();
+
+        // Finish
+
+        Genealogy::from_timed_steps(group_size, timed_steps)
+    }
+}
+
+/// Changes ``state`` as indicated by ``event``.
+fn apply_event(state: &mut SeedBankState, event: &SeedBankEvent) {
+    let (partition, dormant) = state;
+    let (value_index, new_flag) = match *event {
+        SeedBankEvent::Coalescence(ref step) => {
+            apply_step(partition, step);
+            return;
+        },
+        SeedBankEvent::Dormancy(value_index) => (value_index, true),
+        SeedBankEvent::Resuscitation(value_index) => (value_index, false),
+    };
+    let members: Vec<usize> = partition.set(value_index).map(|(i, _)| i).collect();
+    for member in members {
+        dormant[member] = new_flag;
+    }
+}
+
+impl<R> CMarkovChainTrait<SeedBankState> for SeedBankCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Current state of the process.
+    fn state(&self) -> &SeedBankState {
+        &self.state
+    }
+
+    /// Change the current state of the process.
+    fn set_state(&mut self, state: SeedBankState) -> &mut Self {
+        self.state = state;
+        self
+    }
+}
+
+impl<R> Iterator for SeedBankCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    type Item = (f64, SeedBankState);
+
+    /// Changes the state of the ``SeedBankCoalescent`` to a new state, chosen
+    /// according to the stochastic process.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_step() {
+            Some((time_step, _)) => Some((time_step, self.state.clone())),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn only_active_lineages_coalesce() {
+        let mut coalescent = SeedBankCoalescent::new(6, 4, 2.0, 1.0, rand_pcg::Pcg32::seed_from_u64(0));
+
+        let mut dormant = coalescent.state().1.clone();
+        while let Some((_, event)) = coalescent.next_step() {
+            if let SeedBankEvent::Coalescence(step) = &event {
+                assert!(step.iter().flatten().all(|&i| !dormant[i]));
+            }
+            dormant = coalescent.state().1.clone();
+        }
+        let (partition, dormant) = coalescent.state();
+        assert_eq!(partition.amount_of_sets(), 1);
+        assert!(dormant.iter().all(|&flag| flag == dormant[0]));
+    }
+
+    #[test]
+    fn expected_depth_of_two_active_lineages() {
+        // With c = 1 and K = 1/2, the expected depth is (1 + 1 / K)^2 = 9.
+        let coalescent = SeedBankCoalescent::new(2, 0, 1.0, 0.5, rand_pcg::Pcg32::seed_from_u64(0));
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+
+        let samples = 4000;
+        let mean_depth = (0..samples)
+            .map(|_| coalescent.sample_genealogy(&mut rng).depth())
+            .sum::<f64>() / samples as f64;
+        assert!((mean_depth - 9.0).abs() < 0.6);
+    }
+}
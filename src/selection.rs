@@ -0,0 +1,501 @@
+//! Coalescent with selection.
+//!
+//! Under directional selection, the ancestry of a group depends on the types
+//! of its ancestors. The ancestral selection graph (ASG) of Krone and Neuhauser
+//! (1997) accounts for it: going backwards in time, every pair of lineages
+//! coalesces at rate one and every lineage branches at rate σ / 2 into an
+//! incoming and a continuing lineage, until a single lineage is left, the
+//! ultimate ancestor.
+//!
+//! Types are then assigned forwards in time. The ultimate ancestor has the
+//! stationary distribution of the population, types mutate along the branches
+//! at rate θ / 2, to the favoured allele with probability ν, and at each
+//! branching the descendant inherits from the incoming lineage if it carries
+//! the favoured allele and from the continuing lineage otherwise. Following
+//! the true ancestors of the group gives the embedded genealogy.
+//!
+
+// Types
+use rand_distr::Exp;
+use crate::Genealogy;
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Allele at the selected locus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allele {
+    /// Allele favoured by selection.
+    Favoured,
+    /// Allele disfavoured by selection.
+    Unfavoured,
+}
+
+/// Event of an ancestral selection graph. Nodes are numbered in order of
+/// appearance, the first ones being the individuals of the group.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsgEvent {
+    /// Two nodes find a common ancestor, the parent node.
+    Coalescence {
+        /// Time of the event, measured backwards from the present.
+        time: f64,
+        /// Nodes that coalesce.
+        children: [usize; 2],
+        /// Common ancestor node.
+        parent: usize,
+    },
+    /// A node has two potential parents: the incoming and the continuing one.
+    Branching {
+        /// Time of the event, measured backwards from the present.
+        time: f64,
+        /// Node that branches.
+        child: usize,
+        /// Potential parent nodes, incoming and continuing.
+        parents: [usize; 2],
+    },
+}
+
+/// Ancestral selection graph of a group of individuals, with the types of all
+/// its nodes.
+///
+/// This struct is created by the ``sample_asg`` method on SelectiveCoalescent.
+/// See its documentation for more.
+#[derive(Debug, Clone)]
+pub struct AncestralSelectionGraph {
+    group_size: usize,
+    events: Vec<AsgEvent>, // sorted by time
+    alleles: Vec<Allele>, // of each node
+    true_parents: Vec<Option<usize>>, // of each node
+}
+
+impl AncestralSelectionGraph {
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Events of the graph, sorted by time.
+    pub fn events(&self) -> &[AsgEvent] {
+        &self.events
+    }
+
+    /// Allele of each node of the graph.
+    pub fn alleles(&self) -> &[Allele] {
+        &self.alleles
+    }
+
+    /// Allele carried by each individual of the group.
+    pub fn sample_alleles(&self) -> &[Allele] {
+        &self.alleles[..self.group_size]
+    }
+
+    /// Allele of the ultimate ancestor.
+    pub fn ultimate_ancestor_allele(&self) -> Allele {
+        *self.alleles.last().unwrap()
+    }
+
+    /// Genealogy of the group embedded in the graph, following only the true
+    /// ancestors of the individuals.
+    pub fn genealogy(&self) -> Genealogy {
+        let labels: Vec<usize> = (0..self.group_size).collect();
+        self.relabelled_genealogy(&labels)
+    }
+
+    /// Genealogy of the group embedded in the graph, where individual i of
+    /// the graph is individual ``labels[i]`` of the genealogy.
+    fn relabelled_genealogy(&self, labels: &[usize]) -> Genealogy {
+        // Representative individual of each node that is a true ancestor
+
+        let mut representatives: Vec<Option<usize>> = vec![None; self.alleles.len()];
+        for (representative, &label) in representatives.iter_mut().zip(labels) {
+            *representative = Some(label);
+        }
+
+        let mut timed_steps = Vec::new();
+        for event in &self.events {
+            match *event {
+                AsgEvent::Coalescence { time, children: [child_1, child_2], parent } => {
+                    representatives[parent] = match (representatives[child_1], representatives[child_2]) {
+                        (Some(representative_1), Some(representative_2)) => {
+                            timed_steps.push((time, vec![vec![representative_1, representative_2]]));
+                            Some(representative_1.min(representative_2))
+                        },
+                        (Some(representative), None) | (None, Some(representative)) => Some(representative),
+                        (None, None) => None,
+                    };
+                },
+                AsgEvent::Branching { child, .. } => {
+                    if let Some(true_parent) = self.true_parents[child] {
+                        representatives[true_parent] = representatives[child];
+                    }
+                },
+            }
+        }
+
+        Genealogy::from_timed_steps(self.group_size, timed_steps)
+    }
+}
+
+/// Coalescent with directional selection of a group, through its ancestral
+/// selection graph.
+///
+/// Time is measured in coalescent units. The scaled selection strength is σ
+/// and the scaled mutation rate is θ, with mutations being parent independent.
+///
+/// # Examples
+///
+/// Genealogy of a group of individuals carrying the favoured allele.
+/// ```
+/// use coalescence::{Allele, SelectiveCoalescent};
+///
+/// let group_size = 5;
+/// let coalescent = SelectiveCoalescent::new(group_size, 2.0, 1.0, 0.5);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy_given(&[Allele::Favoured; 5], 10_000, &mut rng)
+///     .expect("No graph gave the requested alleles.");
+///
+/// assert_eq!(genealogy.steps().len(), group_size - 1);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectiveCoalescent {
+    group_size: usize,
+    selection_strength: f64,
+    mutation_rate: f64,
+    favoured_mutation_probability: f64,
+}
+
+impl SelectiveCoalescent {
+    /// Creates a new SelectiveCoalescent, where ``selection_strength`` is σ,
+    /// ``mutation_rate`` is θ and ``favoured_mutation_probability`` is the
+    /// probability that a mutation produces the favoured allele.
+    ///
+    /// # Panics
+    ///
+    /// If ``selection_strength`` is negative, ``mutation_rate`` is not positive
+    /// or ``favoured_mutation_probability`` is not a probability.
+    pub fn new(group_size: usize, selection_strength: f64, mutation_rate: f64, favoured_mutation_probability: f64) -> Self {
+        assert!(selection_strength >= 0.0, "The selection strength must be non-negative.");
+        assert!(mutation_rate > 0.0, "The mutation rate must be positive.");
+        assert!((0.0..=1.0).contains(&favoured_mutation_probability), "The favoured mutation probability must be a probability.");
+
+        SelectiveCoalescent { group_size, selection_strength, mutation_rate, favoured_mutation_probability }
+    }
+
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Scaled selection strength σ.
+    pub fn selection_strength(&self) -> f64 {
+        self.selection_strength
+    }
+
+    /// Scaled mutation rate θ.
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    /// Probability that a mutation produces the favoured allele.
+    pub fn favoured_mutation_probability(&self) -> f64 {
+        self.favoured_mutation_probability
+    }
+
+    /// Mean frequency of the favoured allele in the stationary population,
+    /// which is the probability that the ultimate ancestor carries it.
+    ///
+    /// # Remarks
+    ///
+    /// The stationary density is proportional to x^(a - 1) (1 - x)^(b - 1) e^(σ x),
+    /// where a = θ ν and b = θ (1 - ν), so that the mean is a ratio of
+    /// confluent hypergeometric functions.
+    pub fn stationary_mean(&self) -> f64 {
+        let a = self.mutation_rate * self.favoured_mutation_probability;
+        let b = self.mutation_rate * (1.0 - self.favoured_mutation_probability);
+        let sigma = self.selection_strength;
+
+        a / (a + b) * kummer(a + 1.0, a + b + 1.0, sigma) / kummer(a, a + b, sigma)
+    }
+
+    /// Sample an ancestral selection graph, until the ultimate ancestor, and
+    /// the types of its nodes.
+    pub fn sample_asg<S>(&self, rng: &mut S) -> AncestralSelectionGraph
+    where
+        S: Rng,
+    {
+        // Generate events backwards in time
+
+        let mut lineages: Vec<usize> = (0..self.group_size).collect();
+        let mut node_times = vec![0.0; self.group_size];
+        let mut events = Vec::new();
+        let mut time = 0.0;
+
+        while lineages.len() > 1 {
+            let k = lineages.len();
+            let coalescence_rate = (k * (k - 1) / 2) as f64;
+            let branching_rate = k as f64 * self.selection_strength / 2.0;
+            let total_rate = coalescence_rate + branching_rate;
+
+            time += Exp::new(total_rate).unwrap().sample(rng);
+
+            let parent = node_times.len();
+            if rng.gen::<f64>() * total_rate < coalescence_rate {
+                let pair = rand::seq::index::sample(rng, k, 2);
+                let (first, second) = (pair.index(0).min(pair.index(1)), pair.index(0).max(pair.index(1)));
+                events.push(AsgEvent::Coalescence { time, children: [lineages[first], lineages[second]], parent });
+                node_times.push(time);
+                lineages.swap_remove(second);
+                lineages[first] = parent;
+            } else {
+                let index = rng.gen_range(0, k);
+                events.push(AsgEvent::Branching { time, child: lineages[index], parents: [parent, parent + 1] });
+                node_times.extend([time, time]);
+                lineages[index] = parent;
+                lineages.push(parent + 1);
+            }
+        }
+
+        // Event above each node
+
+        let num_nodes = node_times.len();
+        let mut above: Vec<Option<&AsgEvent>> = vec![None; num_nodes];
+        for event in &events {
+            match *event {
+                AsgEvent::Coalescence { children, .. } => {
+                    above[children[0]] = Some(event);
+                    above[children[1]] = Some(event);
+                },
+                AsgEvent::Branching { child, .. } => above[child] = Some(event),
+            }
+        }
+
+        // Assign types forwards in time, parents appearing after their children
+
+        let mut alleles = vec![Allele::Unfavoured; num_nodes];
+        let mut true_parents = vec![None; num_nodes];
+        for node in (0..num_nodes).rev() {
+            let (inherited, end_time, true_parent) = match above[node] {
+                None => {
+                    let favoured = rng.gen_bool(self.stationary_mean().clamp(0.0, 1.0));
+                    let allele = if favoured { Allele::Favoured } else { Allele::Unfavoured };
+                    (allele, node_times[node], None)
+                },
+                Some(&AsgEvent::Coalescence { time, parent, .. }) => (alleles[parent], time, Some(parent)),
+                Some(&AsgEvent::Branching { time, parents: [incoming, continuing], .. }) => {
+                    match alleles[incoming] {
+                        Allele::Favoured => (Allele::Favoured, time, Some(incoming)),
+                        Allele::Unfavoured => (alleles[continuing], time, Some(continuing)),
+                    }
+                },
+            };
+
+            let mutation_probability = -(-self.mutation_rate / 2.0 * (end_time - node_times[node])).exp_m1();
+            alleles[node] = if rng.gen_bool(mutation_probability) {
+                match rng.gen_bool(self.favoured_mutation_probability) {
+                    true => Allele::Favoured,
+                    false => Allele::Unfavoured,
+                }
+            } else {
+                inherited
+            };
+            true_parents[node] = true_parent;
+        }
+
+        // Finish
+
+        AncestralSelectionGraph { group_size: self.group_size, events, alleles, true_parents }
+    }
+
+    /// Sample the genealogy of the group, embedded in an ancestral selection graph.
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng,
+    {
+        self.sample_asg(rng).genealogy()
+    }
+
+    /// Sample the genealogy of the group conditioned on the allele carried by
+    /// each individual.
+    ///
+    /// Returns ``None`` if the alleles are impossible, because mutations only
+    /// produce one of them, or if none of ``max_attempts`` graphs gives as
+    /// many favoured individuals.
+    ///
+    /// # Remarks
+    ///
+    /// Graphs are sampled until as many individuals carry the favoured allele
+    /// as in ``alleles``. Since individuals are exchangeable, those of the
+    /// accepted graph are then relabelled so that each carries its given
+    /// allele. The probability of acceptance is that of the number of
+    /// favoured individuals, so that ``max_attempts`` must grow with the
+    /// size of the group.
+    ///
+    /// # Panics
+    ///
+    /// If there is not one allele per individual.
+    pub fn sample_genealogy_given<S>(&self, alleles: &[Allele], max_attempts: usize, rng: &mut S) -> Option<Genealogy>
+    where
+        S: Rng,
+    {
+        assert_eq!(alleles.len(), self.group_size, "There must be one allele per individual.");
+
+        // Impossible alleles
+
+        let only_favoured = self.favoured_mutation_probability == 1.0;
+        let only_unfavoured = self.favoured_mutation_probability == 0.0;
+        if (only_favoured && alleles.contains(&Allele::Unfavoured)) || (only_unfavoured && alleles.contains(&Allele::Favoured)) {
+            return None;
+        }
+
+        // Rejection sampling on the number of favoured individuals
+
+        let num_favoured = |alleles: &[Allele]| alleles.iter().filter(|&&allele| allele == Allele::Favoured).count();
+        (0..max_attempts)
+            .map(|_| self.sample_asg(rng))
+            .find(|asg| num_favoured(asg.sample_alleles()) == num_favoured(alleles))
+            .map(|asg| asg.relabelled_genealogy(&relabelling(asg.sample_alleles(), alleles)))
+    }
+}
+
+/// Permutation mapping each individual carrying an allele in ``sampled`` to
+/// an individual carrying the same allele in ``requested``, in order.
+fn relabelling(sampled: &[Allele], requested: &[Allele]) -> Vec<usize> {
+    let positions = |allele: Allele| {
+        requested
+            .iter()
+            .enumerate()
+            .filter(move |&(_, &other)| other == allele)
+            .map(|(index, _)| index)
+    };
+    let mut favoured = positions(Allele::Favoured);
+    let mut unfavoured = positions(Allele::Unfavoured);
+    sampled
+        .iter()
+        .map(|&allele| match allele {
+            Allele::Favoured => favoured.next().unwrap(),
+            Allele::Unfavoured => unfavoured.next().unwrap(),
+        })
+        .collect()
+}
+
+/// Confluent hypergeometric function M(a, b, z), computed by its series.
+fn kummer(a: f64, b: f64, z: f64) -> f64 {
+    let mut term: f64 = 1.0;
+    let mut sum: f64 = 1.0;
+    let mut n = 0.0;
+    while term.abs() > 1e-16 * sum.abs() {
+        term *= (a + n) / (b + n) * z / (n + 1.0);
+        sum += term;
+        n += 1.0;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn kummer_function() {
+        assert!((kummer(1.5, 1.5, 2.0) - 2.0_f64.exp()).abs() < 1e-10);
+        assert!((kummer(1.0, 2.0, 3.0) - 3.0_f64.exp_m1() / 3.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn embedded_genealogy_is_a_tree() {
+        let group_size = 7;
+        let coalescent = SelectiveCoalescent::new(group_size, 5.0, 0.5, 0.5);
+        let asg = coalescent.sample_asg(&mut rand_pcg::Pcg32::seed_from_u64(2));
+        let genealogy = asg.genealogy();
+
+        assert!(asg.events().iter().any(|event| matches!(event, AsgEvent::Branching { .. })));
+        assert_eq!(genealogy.steps().len(), group_size - 1);
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+        assert!(genealogy.depth() <= asg.events().iter().map(|event| match *event {
+            AsgEvent::Coalescence { time, .. } | AsgEvent::Branching { time, .. } => time,
+        }).fold(0.0, f64::max));
+    }
+
+    #[test]
+    fn neutral_graph_is_kingman() {
+        let group_size = 5;
+        let neutral = SelectiveCoalescent::new(group_size, 0.0, 1.0, 0.3);
+        assert!((neutral.stationary_mean() - 0.3).abs() < 1e-10);
+
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(6);
+        let samples = 5000;
+        let mean_depth = (0..samples)
+            .map(|_| neutral.sample_genealogy(&mut rng).depth())
+            .sum::<f64>() / samples as f64;
+
+        // Expected 2 (1 - 1/n)
+        let expected = 2.0 * (1.0 - 1.0 / group_size as f64);
+        assert!((mean_depth / expected - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn selection_favours_the_allele() {
+        let coalescent = SelectiveCoalescent::new(5, 4.0, 1.0, 0.3);
+        let expected = coalescent.stationary_mean();
+        assert!(expected > 0.3);
+
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(7);
+        let samples = 5000;
+        let favoured = (0..samples)
+            .filter(|_| coalescent.sample_asg(&mut rng).sample_alleles()[0] == Allele::Favoured)
+            .count();
+        assert!((favoured as f64 / samples as f64 - expected).abs() < 0.03);
+    }
+
+    #[test]
+    fn conditioned_genealogy() {
+        let coalescent = SelectiveCoalescent::new(3, 2.0, 1.0, 0.5);
+        let alleles = [Allele::Favoured, Allele::Unfavoured, Allele::Favoured];
+        let genealogy = coalescent.sample_genealogy_given(&alleles, 10_000, &mut rand_pcg::Pcg32::seed_from_u64(8));
+
+        assert_eq!(genealogy.unwrap().steps().len(), 2);
+    }
+
+    #[test]
+    fn conditioned_genealogy_of_mixed_group() {
+        let coalescent = SelectiveCoalescent::new(12, 2.0, 1.0, 0.5);
+        let alleles: Vec<Allele> = (0..12)
+            .map(|i| if i % 2 == 0 { Allele::Favoured } else { Allele::Unfavoured })
+            .collect();
+        let genealogy = coalescent.sample_genealogy_given(&alleles, 1000, &mut rand_pcg::Pcg32::seed_from_u64(10));
+
+        let genealogy = genealogy.unwrap();
+        assert_eq!(genealogy.steps().len(), 11);
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+    }
+
+    #[test]
+    fn relabelling_matches_alleles() {
+        use Allele::{Favoured, Unfavoured};
+        let sampled = [Favoured, Favoured, Unfavoured, Unfavoured, Favoured];
+        let requested = [Unfavoured, Favoured, Favoured, Favoured, Unfavoured];
+        let labels = relabelling(&sampled, &requested);
+
+        let mut sorted = labels.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+        for (individual, &label) in labels.iter().enumerate() {
+            assert_eq!(requested[label], sampled[individual]);
+        }
+    }
+
+    #[test]
+    fn impossible_alleles_are_rejected() {
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(9);
+        let only_favoured = SelectiveCoalescent::new(3, 2.0, 1.0, 1.0);
+        let only_unfavoured = SelectiveCoalescent::new(3, 2.0, 1.0, 0.0);
+        let mixed = [Allele::Favoured, Allele::Unfavoured, Allele::Favoured];
+
+        assert!(only_favoured.sample_genealogy_given(&mixed, usize::MAX, &mut rng).is_none());
+        assert!(only_unfavoured.sample_genealogy_given(&mixed, usize::MAX, &mut rng).is_none());
+        assert!(only_favoured.sample_genealogy_given(&[Allele::Favoured; 3], 1, &mut rng).is_some());
+    }
+}
@@ -0,0 +1,331 @@
+//! Sequentially Markov coalescent.
+//!
+//! Approximations of the coalescent with recombination that walk along the
+//! sequence, changing the current genealogy at each recombination breakpoint.
+//! At a breakpoint, a point is chosen uniformly on the branches of the current
+//! genealogy, the branch above it is cut and the floating lineage coalesces
+//! again with the rest of the genealogy, at rate one with each lineage present.
+//!
+//! In the SMC (McVean and Cardin, 2005) the floating lineage can not coalesce
+//! with the branch it was cut from. In the SMC' (Marjoram and Wall, 2006) it can,
+//! in which case the genealogy does not change.
+//!
+
+// Types
+use rand_distr::{Exp, Exp1};
+use crate::Genealogy;
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Variant of the sequentially Markov coalescent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmcVariant {
+    /// The floating lineage can not coalesce with its own branch.
+    Smc,
+    /// The floating lineage can coalesce with its own branch.
+    SmcPrime,
+}
+
+/// Binary tree with nodes given by their parent and time. The first nodes
+/// are the individuals of the group, at time zero.
+#[derive(Debug, Clone)]
+struct Tree {
+    parents: Vec<Option<usize>>,
+    times: Vec<f64>,
+}
+
+impl Tree {
+    /// Standard coalescent tree of ``group_size`` individuals.
+    fn sample<S: Rng>(group_size: usize, rng: &mut S) -> Self {
+        let mut parents = vec![None; group_size];
+        let mut times = vec![0.0; group_size];
+        let mut lineages: Vec<usize> = (0..group_size).collect();
+        let mut time = 0.0;
+
+        while lineages.len() > 1 {
+            let k = lineages.len();
+            time += Exp::new((k * (k - 1) / 2) as f64).unwrap().sample(rng);
+            let pair = rand::seq::index::sample(rng, k, 2);
+            let (first, second) = (pair.index(0).min(pair.index(1)), pair.index(0).max(pair.index(1)));
+            let parent = parents.len();
+            parents.push(None);
+            times.push(time);
+            parents[lineages[first]] = Some(parent);
+            parents[lineages[second]] = Some(parent);
+            lineages.swap_remove(second);
+            lineages[first] = parent;
+        }
+
+        Tree { parents, times }
+    }
+
+    /// Time at which the branch above ``node`` ends, infinite for the root.
+    fn end_time(&self, node: usize) -> f64 {
+        match self.parents[node] {
+            Some(parent) => self.times[parent],
+            None => f64::INFINITY,
+        }
+    }
+
+    /// Total length of the branches, excluding the infinite branch above the root.
+    fn length(&self) -> f64 {
+        (0..self.parents.len())
+            .filter_map(|node| self.parents[node].map(|parent| self.times[parent] - self.times[node]))
+            .sum()
+    }
+
+    fn genealogy(&self, group_size: usize) -> Genealogy {
+        let mut internal_nodes: Vec<usize> = (group_size..self.parents.len()).collect();
+        internal_nodes.sort_by(|&a, &b| self.times[a].partial_cmp(&self.times[b]).unwrap());
+
+        // Each internal node is represented by its smallest individual
+        let mut representatives: Vec<usize> = (0..self.parents.len()).collect();
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); self.parents.len()];
+        for node in 0..self.parents.len() {
+            if let Some(parent) = self.parents[node] {
+                children[parent].push(node);
+            }
+        }
+
+        let timed_steps = internal_nodes
+            .into_iter()
+            .map(|node| {
+                let merger: Vec<usize> = children[node].iter().map(|&child| representatives[child]).collect();
+                representatives[node] = *merger.iter().min().unwrap();
+                (self.times[node], vec![merger])
+            })
+            .collect();
+
+        Genealogy::from_timed_steps(group_size, timed_steps)
+    }
+}
+
+/// Sequentially Markov coalescent of a group along a sequence.
+///
+/// Time is measured in coalescent units, and recombination breakpoints
+/// appear at rate ``recombination_rate`` per unit of sequence length and
+/// per unit of length of the current genealogy.
+///
+/// # Examples
+///
+/// ```
+/// use coalescence::{SequentiallyMarkovCoalescent, SmcVariant};
+///
+/// let coalescent = SequentiallyMarkovCoalescent::new(10, 100.0, 0.1, SmcVariant::SmcPrime);
+/// let mut rng = rand::thread_rng();
+///
+/// let depths: Vec<(f64, f64)> = coalescent.sample_genealogies(&mut rng)
+///     .iter()
+///     .map(|((left, right), genealogy)| (right - left, genealogy.depth()))
+///     .collect();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequentiallyMarkovCoalescent {
+    group_size: usize,
+    sequence_length: f64,
+    recombination_rate: f64,
+    variant: SmcVariant,
+}
+
+impl SequentiallyMarkovCoalescent {
+    /// Creates a new SequentiallyMarkovCoalescent.
+    ///
+    /// # Panics
+    ///
+    /// If ``sequence_length`` is not positive or ``recombination_rate`` is negative.
+    pub fn new(group_size: usize, sequence_length: f64, recombination_rate: f64, variant: SmcVariant) -> Self {
+        assert!(sequence_length > 0.0, "The length of the sequence must be positive.");
+        assert!(recombination_rate >= 0.0, "The recombination rate must be non-negative.");
+
+        SequentiallyMarkovCoalescent { group_size, sequence_length, recombination_rate, variant }
+    }
+
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Length of the sequence.
+    pub fn sequence_length(&self) -> f64 {
+        self.sequence_length
+    }
+
+    /// Rate of recombination per unit of sequence length.
+    pub fn recombination_rate(&self) -> f64 {
+        self.recombination_rate
+    }
+
+    /// Variant of the approximation.
+    pub fn variant(&self) -> SmcVariant {
+        self.variant
+    }
+
+    /// Sample the genealogies along the sequence, together with the interval
+    /// ``(left, right)`` each of them covers. Intervals are sorted and cover
+    /// the whole sequence.
+    pub fn sample_genealogies<S>(&self, rng: &mut S) -> Vec<((f64, f64), Genealogy)>
+    where
+        S: Rng,
+    {
+        let mut tree = Tree::sample(self.group_size, rng);
+        let mut genealogies = Vec::new();
+        let mut left = 0.0;
+        let mut position = 0.0;
+
+        loop {
+            // Distance to the next breakpoint
+
+            let rate = self.recombination_rate * tree.length();
+            if rate > 0.0 {
+                position += Exp::new(rate).unwrap().sample(rng);
+            } else {
+                position = f64::INFINITY;
+            }
+            if position >= self.sequence_length {
+                break;
+            }
+
+            // Change the genealogy
+
+            let genealogy = tree.genealogy(self.group_size);
+            if self.recombine(&mut tree, rng) {
+                genealogies.push(((left, position), genealogy));
+                left = position;
+            }
+        }
+
+        genealogies.push(((left, self.sequence_length), tree.genealogy(self.group_size)));
+        genealogies
+    }
+
+    /// Cuts the tree at a uniform point of its branches and lets the floating
+    /// lineage coalesce again. Returns whether the tree changed.
+    fn recombine<S>(&self, tree: &mut Tree, rng: &mut S) -> bool
+    where
+        S: Rng,
+    {
+        // Choose the point
+
+        let mut remaining = rng.gen::<f64>() * tree.length();
+        let mut cut_node = 0;
+        for node in 0..tree.parents.len() {
+            if tree.parents[node].is_some() {
+                cut_node = node;
+                let branch_length = tree.end_time(node) - tree.times[node];
+                if remaining < branch_length {
+                    break;
+                }
+                remaining -= branch_length;
+            }
+        }
+        let cut_time = tree.times[cut_node] + remaining;
+        let old_parent = tree.parents[cut_node].unwrap();
+        let own_branch_end = tree.times[old_parent];
+
+        // Prune the branch above the point
+
+        let mut pruned = tree.clone();
+        let sibling = (0..tree.parents.len())
+            .find(|&node| node != cut_node && tree.parents[node] == Some(old_parent))
+            .unwrap();
+        pruned.parents[sibling] = tree.parents[old_parent];
+        pruned.parents[old_parent] = None;
+        pruned.parents[cut_node] = None;
+        let own_branch = match self.variant {
+            SmcVariant::Smc => None,
+            SmcVariant::SmcPrime => Some(own_branch_end),
+        };
+        let alive_at = |time: f64| -> Vec<usize> {
+            (0..pruned.parents.len())
+                .filter(|&node| node != cut_node && node != old_parent)
+                .filter(|&node| pruned.times[node] <= time && time < pruned.end_time(node))
+                .collect()
+        };
+
+        // Simulate the time of coalescence
+
+        let mut change_times: Vec<f64> = (self.group_size..pruned.parents.len())
+            .filter(|&node| node != old_parent)
+            .map(|node| pruned.times[node])
+            .chain(own_branch)
+            .filter(|&time| time > cut_time)
+            .collect();
+        change_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        change_times.push(f64::INFINITY);
+
+        let mut hazard: f64 = Exp1.sample(rng);
+        let mut current_time = cut_time;
+        let mut coalescence_time = cut_time;
+        for next_time in change_times {
+            let own = own_branch.map_or(0, |end| (current_time < end) as usize);
+            let k = (alive_at(current_time).len() + own) as f64;
+            if hazard <= k * (next_time - current_time) {
+                coalescence_time = current_time + hazard / k;
+                break;
+            }
+            hazard -= k * (next_time - current_time);
+            current_time = next_time;
+        }
+
+        // Choose the branch to coalesce with
+
+        let candidates = alive_at(coalescence_time);
+        let own = own_branch.map_or(0, |end| (coalescence_time < end) as usize);
+        let choice = rng.gen_range(0, candidates.len() + own);
+        if choice == candidates.len() {
+            return false;
+        }
+        let target = candidates[choice];
+
+        // Regraft
+
+        pruned.times[old_parent] = coalescence_time;
+        pruned.parents[old_parent] = pruned.parents[target];
+        pruned.parents[target] = Some(old_parent);
+        pruned.parents[cut_node] = Some(old_parent);
+        *tree = pruned;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn genealogies_cover_the_sequence() {
+        let group_size = 6;
+        for &variant in &[SmcVariant::Smc, SmcVariant::SmcPrime] {
+            let coalescent = SequentiallyMarkovCoalescent::new(group_size, 10.0, 1.0, variant);
+            let genealogies = coalescent.sample_genealogies(&mut rand_pcg::Pcg32::seed_from_u64(6));
+
+            assert!(genealogies.len() > 1);
+            assert_eq!((genealogies[0].0).0, 0.0);
+            assert_eq!((genealogies.last().unwrap().0).1, 10.0);
+            for window in genealogies.windows(2) {
+                assert_eq!((window[0].0).1, (window[1].0).0);
+            }
+            for (_, genealogy) in &genealogies {
+                assert_eq!(genealogy.steps().len(), group_size - 1);
+                assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_genealogies_differ() {
+        for &variant in &[SmcVariant::Smc, SmcVariant::SmcPrime] {
+            let coalescent = SequentiallyMarkovCoalescent::new(6, 10.0, 1.0, variant);
+            for seed in 0..20 {
+                let genealogies = coalescent.sample_genealogies(&mut rand_pcg::Pcg32::seed_from_u64(seed));
+
+                for window in genealogies.windows(2) {
+                    assert_ne!(window[0].1.time_steps(), window[1].1.time_steps());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Multispecies coalescent.
+//!
+//! Gene trees of individuals sampled from several species, embedded in the
+//! tree of the species. Going backwards in time, lineages coalesce within
+//! each branch of the species tree as in a ``Coalescent`` with the population
+//! size of the branch, independently of other branches. At a speciation, the
+//! lineages that are left in the descendant species are pooled into the
+//! ancestral species. Lineages of different species can only coalesce above
+//! their speciation, and may do so in an order that differs from the species
+//! tree: this is incomplete lineage sorting.
+//!
+
+// Types
+use crate::{Coalescent, Demography, Genealogy, Step};
+
+// Traits
+use markovian::traits::CMarkovChainTrait;
+use rand::Rng;
+use core::fmt::Debug;
+
+/// Tree of species, where each branch has its own population size.
+///
+/// Extant species live at time zero and ancestral species start at the time
+/// of their speciation, measured backwards from the present. The branch of
+/// each species goes from its start to the start of its parent, or forever
+/// for the root.
+///
+/// # Examples
+///
+/// The tree ((A, B), C).
+/// ```
+/// let mut species_tree = coalescence::SpeciesTree::new();
+/// let a = species_tree.add_species(1.0);
+/// let b = species_tree.add_species(1.0);
+/// let c = species_tree.add_species(2.0);
+/// let ab = species_tree.add_ancestor(&[a, b], 0.5, 1.0);
+/// let root = species_tree.add_ancestor(&[ab, c], 1.5, 3.0);
+///
+/// assert_eq!(species_tree.root(), root);
+/// assert_eq!(species_tree.parent(a), Some(ab));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpeciesTree {
+    times: Vec<f64>,
+    sizes: Vec<f64>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl SpeciesTree {
+    /// Creates a new SpeciesTree without species.
+    pub fn new() -> Self {
+        SpeciesTree::default()
+    }
+
+    /// Adds an extant species with population size ``size``, returning its index.
+    ///
+    /// # Panics
+    ///
+    /// If ``size`` is not positive.
+    pub fn add_species(&mut self, size: f64) -> usize {
+        self.add_node(Vec::new(), 0.0, size)
+    }
+
+    /// Adds the ancestral species of ``children``, which split at ``time``,
+    /// with population size ``size``. Returns its index.
+    ///
+    /// # Panics
+    ///
+    /// If ``size`` is not positive, if some child does not exist, already has
+    /// an ancestor or starts after ``time``, or if there are no children.
+    pub fn add_ancestor(&mut self, children: &[usize], time: f64, size: f64) -> usize {
+        assert!(!children.is_empty(), "An ancestral species must have descendants.");
+        for &child in children {
+            assert!(child < self.num_species(), "The species {} does not exist.", child);
+            assert!(self.parents[child].is_none(), "The species {} already has an ancestor.", child);
+            assert!(self.times[child] <= time, "A speciation must happen after its descendants start.");
+        }
+
+        let index = self.add_node(children.to_vec(), time, size);
+        for &child in children {
+            self.parents[child] = Some(index);
+        }
+        index
+    }
+
+    fn add_node(&mut self, children: Vec<usize>, time: f64, size: f64) -> usize {
+        assert!(size > 0.0, "Population sizes must be positive.");
+        self.times.push(time);
+        self.sizes.push(size);
+        self.parents.push(None);
+        self.children.push(children);
+        self.times.len() - 1
+    }
+
+    /// Number of species, extant or ancestral.
+    pub fn num_species(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Time at which the branch of ``species`` starts.
+    pub fn time(&self, species: usize) -> f64 {
+        self.times[species]
+    }
+
+    /// Population size along the branch of ``species``.
+    pub fn size(&self, species: usize) -> f64 {
+        self.sizes[species]
+    }
+
+    /// Ancestral species of ``species``, if any.
+    pub fn parent(&self, species: usize) -> Option<usize> {
+        self.parents[species]
+    }
+
+    /// Descendant species of ``species``.
+    pub fn children(&self, species: usize) -> &[usize] {
+        &self.children[species]
+    }
+
+    /// Whether ``species`` is extant, i.e. it has no descendant species.
+    pub fn is_extant(&self, species: usize) -> bool {
+        self.children[species].is_empty()
+    }
+
+    /// The only species without ancestor.
+    ///
+    /// # Panics
+    ///
+    /// If there is not exactly one such species.
+    pub fn root(&self) -> usize {
+        let roots: Vec<usize> = (0..self.num_species())
+            .filter(|&species| self.parents[species].is_none())
+            .collect();
+        assert_eq!(roots.len(), 1, "The species tree must have exactly one root.");
+        roots[0]
+    }
+}
+
+/// Multispecies coalescent of a group of individuals sampled from the extant
+/// species of a ``SpeciesTree``.
+///
+/// Individuals are numbered by species: first the ones of species zero, then
+/// the ones of species one and so on.
+///
+/// # Examples
+///
+/// Incomplete lineage sorting is frequent when speciations are close in time.
+/// ```
+/// let mut species_tree = coalescence::SpeciesTree::new();
+/// let a = species_tree.add_species(1.0);
+/// let b = species_tree.add_species(1.0);
+/// let c = species_tree.add_species(1.0);
+/// let ab = species_tree.add_ancestor(&[a, b], 1.0, 1.0);
+/// species_tree.add_ancestor(&[ab, c], 1.1, 1.0);
+///
+/// let coalescent = coalescence::MultispeciesCoalescent::new(species_tree, vec![1, 1, 1, 0, 0]);
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// // Individuals of different species diverge before their speciation.
+/// assert!(genealogy.divergence(0, 1) > 2.0);
+/// assert!(genealogy.divergence(0, 2) > 2.2);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultispeciesCoalescent {
+    species_tree: SpeciesTree,
+    sample_sizes: Vec<usize>,
+}
+
+impl MultispeciesCoalescent {
+    /// Creates a new MultispeciesCoalescent, with ``sample_sizes[i]``
+    /// individuals sampled from species i.
+    ///
+    /// # Panics
+    ///
+    /// If there is not one sample size per species, if some ancestral species
+    /// is sampled or if the species tree does not have exactly one root.
+    pub fn new(species_tree: SpeciesTree, sample_sizes: Vec<usize>) -> Self {
+        assert_eq!(sample_sizes.len(), species_tree.num_species(), "There must be one sample size per species.");
+        assert!(
+            (0..species_tree.num_species()).all(|species| species_tree.is_extant(species) || sample_sizes[species] == 0),
+            "Only extant species can be sampled."
+        );
+        species_tree.root();
+
+        MultispeciesCoalescent { species_tree, sample_sizes }
+    }
+
+    /// Tree of the species.
+    pub fn species_tree(&self) -> &SpeciesTree {
+        &self.species_tree
+    }
+
+    /// Number of individuals sampled from each species.
+    pub fn sample_sizes(&self) -> &[usize] {
+        &self.sample_sizes
+    }
+
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.sample_sizes.iter().sum()
+    }
+
+    /// Species from which each individual was sampled.
+    pub fn sample_species(&self) -> Vec<usize> {
+        self.sample_sizes
+            .iter()
+            .enumerate()
+            .flat_map(|(species, &size)| (0..size).map(move |_| species))
+            .collect()
+    }
+
+    /// Sample a gene tree: a genealogy of the group embedded in the species tree.
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Species sorted by time, so that descendants come first
+
+        let mut order: Vec<usize> = (0..self.species_tree.num_species()).collect();
+        order.sort_by(|&a, &b| self.species_tree.time(a).partial_cmp(&self.species_tree.time(b)).unwrap());
+
+        // Lineages entering each branch, represented by an individual
+
+        let mut first_individual = 0;
+        let mut lineages: Vec<Vec<usize>> = self.sample_sizes
+            .iter()
+            .map(|&size| {
+                first_individual += size;
+                ((first_individual - size)..first_individual).collect()
+            })
+            .collect();
+
+        // Coalesce within each branch
+
+        let mut timed_steps: Vec<(f64, Step)> = Vec::new();
+        for species in order {
+            let start = self.species_tree.time(species);
+            let duration = match self.species_tree.parent(species) {
+                Some(parent) => self.species_tree.time(parent) - start,
+                None => f64::INFINITY,
+            };
+            let branch_lineages = std::mem::take(&mut lineages[species]);
+
+            let survivors = if branch_lineages.len() > 1 {
+                let mut coalescent_process = Coalescent::new(branch_lineages.len(), rng.clone());
+                coalescent_process.set_demography(Demography::new(self.species_tree.size(species)));
+
+                while let Some((time_step, step)) = coalescent_process.peek_next_step() {
+                    if coalescent_process.time() + time_step > duration {
+                        break;
+                    }
+                    coalescent_process.take_step(time_step, &step);
+                    let step: Step = step
+                        .iter()
+                        .map(|merger| merger.iter().map(|&i| branch_lineages[i]).collect())
+                        .collect();
+                    timed_steps.push((start + coalescent_process.time(), step));
+                }
+                *rng = coalescent_process.rng().clone();
+
+                coalescent_process.state()
+                    .all_sets()
+                    .map(|mut set| branch_lineages[set.next().unwrap().0])
+                    .collect()
+            } else {
+                branch_lineages
+            };
+
+            // Pool into the ancestral species
+
+            if let Some(parent) = self.species_tree.parent(species) {
+                lineages[parent].extend(survivors);
+            }
+        }
+
+        // Finish
+
+        timed_steps.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Genealogy::from_timed_steps(self.group_size(), timed_steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn three_species(internal_branch: f64) -> SpeciesTree {
+        let mut species_tree = SpeciesTree::new();
+        let a = species_tree.add_species(1.0);
+        let b = species_tree.add_species(1.0);
+        let c = species_tree.add_species(1.0);
+        let ab = species_tree.add_ancestor(&[a, b], 1.0, 1.0);
+        species_tree.add_ancestor(&[ab, c], 1.0 + internal_branch, 1.0);
+        species_tree
+    }
+
+    #[test]
+    fn lineages_coalesce_above_speciations() {
+        let coalescent = MultispeciesCoalescent::new(three_species(0.5), vec![3, 2, 2, 0, 0]);
+        assert_eq!(coalescent.sample_species(), vec![0, 0, 0, 1, 1, 2, 2]);
+
+        for seed in 0..20 {
+            let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(seed));
+            assert_eq!(genealogy.steps().len(), 6);
+            assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+            assert!(genealogy.divergence(0, 3) >= 2.0);
+            assert!(genealogy.divergence(3, 5) >= 3.0);
+        }
+    }
+
+    #[test]
+    fn incomplete_lineage_sorting() {
+        // The gene tree of one individual per species differs from the species
+        // tree with probability 2/3 exp(-t), where t is the internal branch.
+        let internal_branch = 0.2;
+        let coalescent = MultispeciesCoalescent::new(three_species(internal_branch), vec![1, 1, 1, 0, 0]);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+
+        let samples = 4000;
+        let discordant = (0..samples)
+            .map(|_| coalescent.sample_genealogy(&mut rng))
+            .filter(|genealogy| !genealogy.path()[1].same_set(0, 1))
+            .count();
+        let expected = 2.0 / 3.0 * (-internal_branch).exp();
+        assert!((discordant as f64 / samples as f64 - expected).abs() < 0.03);
+    }
+}
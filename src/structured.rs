@@ -0,0 +1,594 @@
+//! Structured coalescent process.
+//!
+//! The population is divided in demes, each with its own relative size.
+//! Going backwards in time, each lineage migrates from deme i to deme j at
+//! rate m_ij, given by the migration matrix, and two lineages can only
+//! coalesce if they are in the same deme. With k lineages in a deme of
+//! relative size λ, the rate of coalescence in this deme is k (k - 1) / (2 λ).
+//!
+//! Demes can also be related by events at given times: splits, where going
+//! backwards all lineages of a deme move to its ancestral deme, and admixture
+//! pulses, where each lineage of a deme moves to a source deme with some
+//! probability.
+//!
+
+// Types
+use partitions::PartitionVec;
+use rand_distr::Exp;
+use rand::distributions::WeightedIndex;
+use crate::{Genealogy, Step, Topology};
+use crate::genealogy::apply_step;
+
+// Traits
+use markovian::traits::CMarkovChainTrait;
+use rand::distributions::Distribution;
+use rand::Rng;
+use core::fmt::Debug;
+use std::iter::FromIterator;
+
+/// State of a structured coalescent: a partition of the group and the deme
+/// of the lineage carrying each individual.
+pub type StructuredState = (PartitionVec<()>, Vec<usize>);
+
+/// Transition of the structured coalescent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StructuredEvent {
+    /// Sets of the partition joint in a deme.
+    Coalescence(Step),
+    /// Lineages, given by one value index of their set, moving to a new deme.
+    Migration(Vec<(usize, usize)>),
+}
+
+/// Event changing the demes of many lineages at once, at a given time
+/// measured backwards from the present.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DemeEvent {
+    /// The deme ``derived`` split from ``ancestral``: going backwards, all
+    /// lineages in ``derived`` move to ``ancestral``. Afterwards, lineages do
+    /// not migrate into ``derived`` anymore.
+    Split {
+        /// Time of the split.
+        time: f64,
+        /// Deme that ceases to exist, going backwards.
+        derived: usize,
+        /// Deme that receives the lineages.
+        ancestral: usize,
+    },
+    /// The deme ``admixed`` received a pulse of migrants from ``source``:
+    /// going backwards, each lineage in ``admixed`` moves to ``source`` with
+    /// probability ``proportion``.
+    Admixture {
+        /// Time of the pulse.
+        time: f64,
+        /// Deme that received migrants.
+        admixed: usize,
+        /// Deme the migrants came from.
+        source: usize,
+        /// Proportion of ``admixed`` made of migrants.
+        proportion: f64,
+    },
+}
+
+impl DemeEvent {
+    /// Creates a new split of ``derived`` from ``ancestral``.
+    ///
+    /// # Panics
+    ///
+    /// If ``time`` is not positive or both demes are the same.
+    pub fn split(time: f64, derived: usize, ancestral: usize) -> Self {
+        assert!(time > 0.0, "Events must happen at positive times.");
+        assert_ne!(derived, ancestral, "A deme can not split from itself.");
+        DemeEvent::Split { time, derived, ancestral }
+    }
+
+    /// Creates a new admixture pulse from ``source`` into ``admixed``.
+    ///
+    /// # Panics
+    ///
+    /// If ``time`` is not positive, both demes are the same or ``proportion``
+    /// is not a probability.
+    pub fn admixture(time: f64, admixed: usize, source: usize, proportion: f64) -> Self {
+        assert!(time > 0.0, "Events must happen at positive times.");
+        assert_ne!(admixed, source, "A deme can not be admixed with itself.");
+        assert!((0.0..=1.0).contains(&proportion), "The admixture proportion must be a probability.");
+        DemeEvent::Admixture { time, admixed, source, proportion }
+    }
+
+    /// Time of the event.
+    pub fn time(&self) -> f64 {
+        match *self {
+            DemeEvent::Split { time, .. } => time,
+            DemeEvent::Admixture { time, .. } => time,
+        }
+    }
+
+    /// Largest deme involved in the event.
+    fn max_deme(&self) -> usize {
+        match *self {
+            DemeEvent::Split { derived, ancestral, .. } => derived.max(ancestral),
+            DemeEvent::Admixture { admixed, source, .. } => admixed.max(source),
+        }
+    }
+}
+
+/// Structured coalescent process, where individuals live in demes connected
+/// by migration.
+///
+/// Individuals are numbered consecutively by deme: the first ``sample_sizes[0]``
+/// individuals are sampled from deme 0, the next ``sample_sizes[1]`` from deme 1
+/// and so on.
+///
+/// # Examples
+///
+/// Two demes exchanging migrants.
+/// ```
+/// let sample_sizes = vec![10, 10];
+/// let deme_sizes = vec![1.0, 1.0];
+/// let migration_matrix = vec![vec![0.0, 0.5], vec![0.5, 0.0]];
+/// let rng = rand::thread_rng();
+/// let coalescent = coalescence::StructuredCoalescent::new(&sample_sizes, deme_sizes, migration_matrix, rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// let within = genealogy.mean_divergence_between(0, 0);
+/// let between = genealogy.mean_divergence_between(0, 1);
+/// ```
+///
+/// An out-of-Africa style model: a population splits from an ancestral one,
+/// and later admixes with a third population.
+/// ```
+/// use coalescence::{DemeEvent, StructuredCoalescent};
+///
+/// let migration_matrix = vec![vec![0.0, 0.1, 0.0], vec![0.1, 0.0, 0.0], vec![0.0, 0.0, 0.0]];
+/// let rng = rand::thread_rng();
+/// let mut coalescent = StructuredCoalescent::new(&[5, 5, 5], vec![1.0, 0.2, 0.5], migration_matrix, rng);
+/// coalescent
+///     .add_event(DemeEvent::admixture(0.05, 1, 2, 0.03))
+///     .add_event(DemeEvent::split(0.5, 2, 1))
+///     .add_event(DemeEvent::split(1.0, 1, 0));
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// assert_eq!(genealogy.sample_demes().unwrap()[5..10], [1; 5]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StructuredCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    state: StructuredState,
+    sample_sizes: Vec<usize>,
+    deme_sizes: Vec<f64>,
+    migration_matrix: Vec<Vec<f64>>,
+    events: Vec<DemeEvent>,
+    applied_events: usize,
+    time: f64,
+    rng: R,
+}
+
+impl<R> StructuredCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new StructuredCoalescent.
+    ///
+    /// The entry ``migration_matrix[i][j]`` is the rate at which a lineage in
+    /// deme i migrates to deme j, backwards in time. Diagonal entries are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If the number of demes given by ``sample_sizes``, ``deme_sizes`` and
+    /// ``migration_matrix`` differ, if some deme size is not positive or if
+    /// some migration rate is negative.
+    pub fn new(sample_sizes: &[usize], deme_sizes: Vec<f64>, migration_matrix: Vec<Vec<f64>>, rng: R) -> Self {
+        let num_demes = deme_sizes.len();
+        assert_eq!(sample_sizes.len(), num_demes, "There must be one sample size per deme.");
+        assert_eq!(migration_matrix.len(), num_demes, "The migration matrix must have one row per deme.");
+        assert!(migration_matrix.iter().all(|row| row.len() == num_demes), "The migration matrix must be square.");
+        assert!(deme_sizes.iter().all(|&size| size > 0.0), "The size of every deme must be positive.");
+        assert!(migration_matrix.iter().flatten().all(|&rate| rate >= 0.0), "Migration rates must be non-negative.");
+
+        let demes: Vec<usize> = sample_sizes
+            .iter()
+            .enumerate()
+            .flat_map(|(deme, &sample_size)| (0..sample_size).map(move |_| deme))
+            .collect();
+        let partition: PartitionVec<()> = PartitionVec::from_iter(demes.iter().map(|_| ()));
+
+        StructuredCoalescent {
+            state: (partition, demes),
+            sample_sizes: sample_sizes.to_vec(),
+            deme_sizes,
+            migration_matrix,
+            events: Vec::new(),
+            applied_events: 0,
+            time: 0.0,
+            rng,
+        }
+    }
+
+    /// Creates a new StructuredCoalescent with demes of relative size one,
+    /// connected as in ``topology``, where each lineage leaves its deme at
+    /// rate ``migration_rate``.
+    ///
+    /// # Panics
+    ///
+    /// If the number of sample sizes differs from the number of demes of the
+    /// topology or if ``migration_rate`` is negative.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::{StructuredCoalescent, Topology};
+    ///
+    /// let topology = Topology::Island { demes: 3 };
+    /// let rng = rand::thread_rng();
+    /// let coalescent = StructuredCoalescent::with_topology(&[2, 2, 2], &topology, 0.5, rng);
+    ///
+    /// assert_eq!(coalescent.migration_matrix()[0], vec![0.0, 0.25, 0.25]);
+    /// ```
+    pub fn with_topology(sample_sizes: &[usize], topology: &Topology, migration_rate: f64, rng: R) -> Self {
+        let deme_sizes = vec![1.0; topology.num_demes()];
+        StructuredCoalescent::new(sample_sizes, deme_sizes, topology.migration_matrix(migration_rate), rng)
+    }
+
+    /// Current time of the process, measured backwards from the present.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Number of individuals sampled from each deme.
+    pub fn sample_sizes(&self) -> &[usize] {
+        &self.sample_sizes
+    }
+
+    /// Relative size of each deme.
+    pub fn deme_sizes(&self) -> &[f64] {
+        &self.deme_sizes
+    }
+
+    /// Backward migration rates between demes.
+    pub fn migration_matrix(&self) -> &[Vec<f64>] {
+        &self.migration_matrix
+    }
+
+    /// Events changing the demes of many lineages at once, sorted by time.
+    pub fn events(&self) -> &[DemeEvent] {
+        &self.events
+    }
+
+    /// Adds an event changing the demes of many lineages at once. Events at
+    /// the same time happen in the order they were added.
+    ///
+    /// # Panics
+    ///
+    /// If the event involves a deme that does not exist.
+    pub fn add_event(&mut self, event: DemeEvent) -> &mut Self {
+        assert!(event.max_deme() < self.deme_sizes.len(), "The event involves a deme that does not exist.");
+        let position = self.events.partition_point(|other| other.time() <= event.time());
+        self.events.insert(position, event);
+        self
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Peeks a possible next event, chosen according to the stochastic process.
+    /// This does not change the state of the ``StructuredCoalescent``.
+    ///
+    /// # Panics
+    ///
+    /// If there are several lineages, but none of them can coalesce or migrate
+    /// and no event is left.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::StructuredEvent;
+    /// let rng = rand::thread_rng();
+    /// let mut coalescent = coalescence::StructuredCoalescent::new(&[1, 1], vec![1.0, 1.0], vec![vec![0.0, 1.0], vec![1.0, 0.0]], rng);
+    ///
+    /// // Individuals in different demes can not coalesce.
+    /// match coalescent.peek_next_step().unwrap() {
+    ///     (_time, StructuredEvent::Migration(migrations)) => assert_eq!(migrations.len(), 1),
+    ///     (_time, StructuredEvent::Coalescence(_)) => unreachable!(),
+    /// }
+    /// ```
+    pub fn peek_next_step(&mut self) -> Option<(f64, StructuredEvent)> {
+        self.peek_next_transition()
+            .map(|(time_step, event, _)| (time_step, event))
+    }
+
+    /// Peeks a possible next event, also telling whether it comes from the
+    /// next ``DemeEvent``.
+    fn peek_next_transition(&mut self) -> Option<(f64, StructuredEvent, bool)> {
+        let (partition, demes) = &self.state;
+        let lineages: Vec<usize> = partition
+            .all_sets()
+            .map(|mut set| set.next().unwrap().0)
+            .collect();
+
+        if lineages.len() == 1 {
+            None
+        } else {
+            // Compute rates
+
+            let num_demes = self.deme_sizes.len();
+            let mut lineages_per_deme: Vec<Vec<usize>> = vec![Vec::new(); num_demes];
+            for &lineage in &lineages {
+                lineages_per_deme[demes[lineage]].push(lineage);
+            }
+            let coalescence_rates = lineages_per_deme
+                .iter()
+                .zip(&self.deme_sizes)
+                .map(|(deme_lineages, size)| {
+                    let k = deme_lineages.len();
+                    (k * k.saturating_sub(1) / 2) as f64 / size
+                });
+            let active_demes = self.active_demes();
+            let migration_weights: Vec<Vec<f64>> = self.migration_matrix
+                .iter()
+                .enumerate()
+                .map(|(deme, row)| {
+                    row.iter()
+                        .enumerate()
+                        .map(|(other_deme, &rate)| if other_deme == deme || !active_demes[other_deme] { 0.0 } else { rate })
+                        .collect()
+                })
+                .collect();
+            let migration_rates = lineages
+                .iter()
+                .map(|&lineage| migration_weights[demes[lineage]].iter().sum::<f64>());
+            let rates: Vec<f64> = coalescence_rates.chain(migration_rates).collect();
+            let total_rate: f64 = rates.iter().sum();
+
+            // Simulate time step
+
+            let time_step = if total_rate > 0.0 {
+                Exp::new(total_rate).unwrap().sample(&mut self.rng)
+            } else {
+                f64::INFINITY
+            };
+
+            // Check for deme events before the step
+
+            if let Some(deme_event) = self.events.get(self.applied_events) {
+                if deme_event.time() <= self.time + time_step {
+                    let time_step = deme_event.time() - self.time;
+                    let rng = &mut self.rng;
+                    let migrations = lineages
+                        .iter()
+                        .filter_map(|&lineage| match *deme_event {
+                            DemeEvent::Split { derived, ancestral, .. } => {
+                                (demes[lineage] == derived).then_some((lineage, ancestral))
+                            },
+                            DemeEvent::Admixture { admixed, source, proportion, .. } => {
+                                (demes[lineage] == admixed && rng.gen_bool(proportion)).then_some((lineage, source))
+                            },
+                        })
+                        .collect();
+                    return Some((time_step, StructuredEvent::Migration(migrations), true));
+                }
+            }
+            assert!(total_rate > 0.0, "Lineages in different demes can never meet.");
+
+            // Choose between possible transitions
+
+            let event_index = WeightedIndex::new(&rates).unwrap().sample(&mut self.rng);
+            let event = if event_index < num_demes {
+                let deme_lineages = &lineages_per_deme[event_index];
+                let pair = rand::seq::index::sample(&mut self.rng, deme_lineages.len(), 2);
+                StructuredEvent::Coalescence(vec![vec![deme_lineages[pair.index(0)], deme_lineages[pair.index(1)]]])
+            } else {
+                let lineage = lineages[event_index - num_demes];
+                let new_deme = WeightedIndex::new(&migration_weights[demes[lineage]]).unwrap().sample(&mut self.rng);
+                StructuredEvent::Migration(vec![(lineage, new_deme)])
+            };
+
+            // Return
+
+            Some((time_step, event, false))
+        }
+    }
+
+    /// Whether each deme still exists at the current time, i.e. it did not
+    /// split from another deme yet, going backwards.
+    fn active_demes(&self) -> Vec<bool> {
+        let mut active_demes = vec![true; self.deme_sizes.len()];
+        for event in &self.events[..self.applied_events] {
+            if let DemeEvent::Split { derived, .. } = *event {
+                active_demes[derived] = false;
+            }
+        }
+        active_demes
+    }
+
+    /// Changes to a next state of the ``StructuredCoalescent``, chosen
+    /// according to the stochastic process and returning the event that
+    /// produced this next state.
+    pub fn next_step(&mut self) -> Option<(f64, StructuredEvent)> {
+        match self.peek_next_transition() {
+            Some((time_step, event, from_deme_event)) => {
+                apply_event(&mut self.state, &event);
+                if from_deme_event {
+                    self.time = self.events[self.applied_events].time();
+                    self.applied_events += 1;
+                } else {
+                    self.time += time_step;
+                }
+                Some((time_step, event))
+            },
+            None => None,
+        }
+    }
+
+    /// Sample a genealogy: from the initial state until there is only one set
+    /// in the partition. Returns a ``Genealogy`` that records the deme of every
+    /// lineage through time. Migrations are steps of the genealogy without mergers.
+    ///
+    /// # Remarks
+    ///
+    /// No internal state changes, including the internal
+    /// random number generator. This is why this methods requires a rng.
+    ///
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Initialize a StructuredCoalescent
+
+        let mut coalescent_process = self.restart(rng.clone());
+
+        // Generate transitions
+
+        let mut state = coalescent_process.state().clone();
+        let mut path = vec![state.0.clone()];
+        let mut demes = vec![state.1.clone()];
+        let mut steps: Vec<Step> = Vec::new();
+        let mut time_steps: Vec<f64> = Vec::new();
+
+        while let Some((time_step, event)) = coalescent_process.next_step() {
+            apply_event(&mut state, &event);
+            let step = match event {
+                StructuredEvent::Coalescence(step) => step,
+                StructuredEvent::Migration(_) => Vec::new(),
+            };
+
+            path.push(state.0.clone());
+            demes.push(state.1.clone());
+            steps.push(step);
+            time_steps.push(time_step);
+        }
+
+        // Update rng
+
+        *rng = coalescent_process.rng.clone();
+
+        // Finish
+
+        Genealogy::new(path, steps, time_steps).with_demes(demes)
+    }
+
+    /// New process with the same structure and events, starting at time zero
+    /// from the initial sample.
+    fn restart<S>(&self, rng: S) -> StructuredCoalescent<S>
+    where
+        S: Rng + Clone + Debug,
+    {
+        let mut coalescent_process = StructuredCoalescent::new(&self.sample_sizes, self.deme_sizes.clone(), self.migration_matrix.clone(), rng);
+        coalescent_process.events = self.events.clone();
+        coalescent_process
+    }
+}
+
+/// Changes ``state`` as indicated by ``event``.
+fn apply_event(state: &mut StructuredState, event: &StructuredEvent) {
+    let (partition, demes) = state;
+    match event {
+        StructuredEvent::Coalescence(step) => apply_step(partition, step),
+        StructuredEvent::Migration(migrations) => {
+            for &(value_index, new_deme) in migrations {
+                let members: Vec<usize> = partition.set(value_index).map(|(i, _)| i).collect();
+                for member in members {
+                    demes[member] = new_deme;
+                }
+            }
+        },
+    }
+}
+
+impl<R> CMarkovChainTrait<StructuredState> for StructuredCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Current state of the process.
+    fn state(&self) -> &StructuredState {
+        &self.state
+    }
+
+    /// Change the current state of the process.
+    fn set_state(&mut self, state: StructuredState) -> &mut Self {
+        self.state = state;
+        self
+    }
+}
+
+impl<R> Iterator for StructuredCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    type Item = (f64, StructuredState);
+
+    /// Changes the state of the ``StructuredCoalescent`` to a new state, chosen
+    /// according to the stochastic process.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_step() {
+            Some((time_step, _)) => Some((time_step, self.state.clone())),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn coalescence_within_demes() {
+        let sample_sizes = [5, 3, 4];
+        let migration_matrix = vec![vec![0.0, 1.0, 0.0], vec![1.0, 0.0, 1.0], vec![0.0, 1.0, 0.0]];
+        let rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let coalescent = StructuredCoalescent::new(&sample_sizes, vec![1.0, 0.5, 2.0], migration_matrix, rng);
+
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(3));
+        let demes = genealogy.demes().unwrap();
+
+        assert_eq!(demes[0], vec![0, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+        for (generation, step) in genealogy.steps().iter().enumerate() {
+            for merger in step {
+                assert_eq!(demes[generation][merger[0]], demes[generation][merger[1]]);
+            }
+        }
+    }
+
+    #[test]
+    fn splits_join_isolated_demes() {
+        let no_migration = vec![vec![0.0; 3]; 3];
+        let mut coalescent = StructuredCoalescent::new(&[3, 3, 3], vec![1.0; 3], no_migration, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent
+            .add_event(DemeEvent::split(2.0, 2, 0))
+            .add_event(DemeEvent::split(1.0, 1, 0));
+        assert_eq!(coalescent.events()[0], DemeEvent::split(1.0, 1, 0));
+
+        for seed in 0..10 {
+            let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(seed));
+            assert_eq!(genealogy.sample_demes().unwrap(), &[0, 0, 0, 1, 1, 1, 2, 2, 2]);
+            assert!(genealogy.divergence(0, 3) >= 2.0);
+            assert!(genealogy.divergence(3, 6) >= 4.0);
+            assert!(genealogy.depth() >= 2.0);
+        }
+    }
+
+    #[test]
+    fn admixture_moves_a_proportion_of_lineages() {
+        let no_migration = vec![vec![0.0; 2]; 2];
+        let mut coalescent = StructuredCoalescent::new(&[1000, 0], vec![1e6, 1.0], no_migration, rand_pcg::Pcg32::seed_from_u64(0));
+        coalescent.add_event(DemeEvent::admixture(1e-9, 0, 1, 0.3));
+
+        match coalescent.next_step().unwrap() {
+            (_, StructuredEvent::Migration(migrations)) => {
+                assert!((migrations.len() as f64 / 1000.0 - 0.3).abs() < 0.05);
+                assert!(migrations.iter().all(|&(_, deme)| deme == 1));
+            },
+            (_, StructuredEvent::Coalescence(_)) => unreachable!(),
+        }
+        assert_eq!(coalescent.time(), 1e-9);
+    }
+}
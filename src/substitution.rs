@@ -0,0 +1,381 @@
+//! Nucleotide substitution models.
+//!
+//! Evolves DNA sequences of a finite number of sites down a genealogy. The
+//! sequence of the common ancestor is drawn from the stationary frequencies
+//! of the model, and each site then changes along every branch following a
+//! continuous-time Markov chain with rate matrix Q.
+//!
+//! Rate matrices are normalized to one expected substitution per unit of
+//! time at stationarity, and a site with population-scaled mutation rate θ
+//! accumulates θ / 2 expected substitutions per unit of time of the genealogy,
+//! as in the infinite-sites model. Rate heterogeneity among sites is modelled
+//! by multiplying the rate of each site by a gamma distributed factor of mean one.
+//!
+
+// Types
+use rand::distributions::WeightedIndex;
+use rand_distr::{Exp, Gamma};
+use crate::Genealogy;
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+
+/// Nucleotides, in the order used by rate matrices and frequencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Nucleotide {
+    A,
+    C,
+    G,
+    T,
+}
+
+impl Nucleotide {
+    const ALL: [Nucleotide; 4] = [Nucleotide::A, Nucleotide::C, Nucleotide::G, Nucleotide::T];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl From<Nucleotide> for char {
+    fn from(nucleotide: Nucleotide) -> Self {
+        match nucleotide {
+            Nucleotide::A => 'A',
+            Nucleotide::C => 'C',
+            Nucleotide::G => 'G',
+            Nucleotide::T => 'T',
+        }
+    }
+}
+
+/// Time-reversible model of nucleotide substitution.
+///
+/// Exchangeabilities of the GTR model are given in the order
+/// A-C, A-G, A-T, C-G, C-T, G-T, and frequencies in the order A, C, G, T.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubstitutionModel {
+    /// Jukes-Cantor: all substitutions have the same rate.
+    Jc69,
+    /// Kimura two-parameter: transitions are ``kappa`` times faster than transversions.
+    K80 {
+        kappa: f64,
+    },
+    /// Hasegawa-Kishino-Yano: K80 with unequal nucleotide frequencies.
+    Hky85 {
+        kappa: f64,
+        frequencies: [f64; 4],
+    },
+    /// General time-reversible model.
+    Gtr {
+        rates: [f64; 6],
+        frequencies: [f64; 4],
+    },
+}
+
+impl SubstitutionModel {
+    /// Stationary frequencies of the nucleotides.
+    pub fn frequencies(&self) -> [f64; 4] {
+        match *self {
+            SubstitutionModel::Jc69 | SubstitutionModel::K80 { .. } => [0.25; 4],
+            SubstitutionModel::Hky85 { frequencies, .. } | SubstitutionModel::Gtr { frequencies, .. } => frequencies,
+        }
+    }
+
+    /// Exchangeabilities, in the order A-C, A-G, A-T, C-G, C-T, G-T.
+    fn exchangeabilities(&self) -> [f64; 6] {
+        match *self {
+            SubstitutionModel::Jc69 => [1.0; 6],
+            SubstitutionModel::K80 { kappa } | SubstitutionModel::Hky85 { kappa, .. } => [1.0, kappa, 1.0, 1.0, kappa, 1.0],
+            SubstitutionModel::Gtr { rates, .. } => rates,
+        }
+    }
+
+    /// Rate matrix Q, normalized to one expected substitution per unit of time.
+    ///
+    /// # Panics
+    ///
+    /// If some parameter is not positive or the frequencies do not add up to one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let q = coalescence::SubstitutionModel::K80 { kappa: 2.0 }.rate_matrix();
+    ///
+    /// // Transitions A-G are twice as fast as transversions A-C.
+    /// assert!((q[0][2] - 2.0 * q[0][1]).abs() < 1e-10);
+    /// assert!((q[0].iter().sum::<f64>()).abs() < 1e-10);
+    /// ```
+    pub fn rate_matrix(&self) -> [[f64; 4]; 4] {
+        let frequencies = self.frequencies();
+        let exchangeabilities = self.exchangeabilities();
+        assert!(frequencies.iter().all(|&frequency| frequency > 0.0), "The frequencies must be positive.");
+        assert!((frequencies.iter().sum::<f64>() - 1.0).abs() < 1e-10, "The frequencies must add up to one.");
+        assert!(exchangeabilities.iter().all(|&rate| rate > 0.0), "The rates must be positive.");
+
+        // Off-diagonal entries
+
+        let pairs = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+        let mut rate_matrix = [[0.0; 4]; 4];
+        for (&(i, j), &rate) in pairs.iter().zip(exchangeabilities.iter()) {
+            rate_matrix[i][j] = rate * frequencies[j];
+            rate_matrix[j][i] = rate * frequencies[i];
+        }
+
+        // Diagonal and normalization
+
+        let mut mean_rate = 0.0;
+        for i in 0..4 {
+            rate_matrix[i][i] = -rate_matrix[i].iter().sum::<f64>();
+            mean_rate -= frequencies[i] * rate_matrix[i][i];
+        }
+        for row in rate_matrix.iter_mut() {
+            row.iter_mut().for_each(|rate| *rate /= mean_rate);
+        }
+
+        rate_matrix
+    }
+}
+
+/// Finite-sites model of DNA sequence evolution.
+///
+/// # Examples
+///
+/// Sequences of 100 sites under HKY85 with gamma rate heterogeneity.
+/// ```
+/// use coalescence::{Coalescent, FiniteSites, SubstitutionModel};
+///
+/// let rng = rand::thread_rng();
+/// let coalescent = Coalescent::new(5, rng);
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// let model = SubstitutionModel::Hky85 { kappa: 4.0, frequencies: [0.3, 0.2, 0.2, 0.3] };
+/// let mut finite_sites = FiniteSites::new(100, 0.01, model);
+/// finite_sites.set_gamma_shape(0.5);
+/// let sequences = finite_sites.sample_sequences(&genealogy, &mut rng);
+///
+/// assert_eq!(sequences.len(), 5);
+/// let first: String = sequences[0].iter().map(|&nucleotide| char::from(nucleotide)).collect();
+/// assert_eq!(first.len(), 100);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiniteSites {
+    sequence_length: usize,
+    mutation_rate: f64,
+    model: SubstitutionModel,
+    gamma_shape: Option<f64>,
+}
+
+impl FiniteSites {
+    /// Creates a new FiniteSites model of sequences of ``sequence_length`` sites,
+    /// each with population-scaled mutation rate θ, without rate heterogeneity.
+    ///
+    /// # Panics
+    ///
+    /// If ``mutation_rate`` is negative or the parameters of ``model`` are not valid.
+    pub fn new(sequence_length: usize, mutation_rate: f64, model: SubstitutionModel) -> Self {
+        assert!(mutation_rate >= 0.0, "The mutation rate must be non-negative.");
+        model.rate_matrix();
+
+        FiniteSites { sequence_length, mutation_rate, model, gamma_shape: None }
+    }
+
+    /// Number of sites of the sequences.
+    pub fn sequence_length(&self) -> usize {
+        self.sequence_length
+    }
+
+    /// Population-scaled mutation rate θ of each site.
+    pub fn mutation_rate(&self) -> f64 {
+        self.mutation_rate
+    }
+
+    /// Substitution model.
+    pub fn model(&self) -> SubstitutionModel {
+        self.model
+    }
+
+    /// Shape of the gamma distribution of rates among sites, if any.
+    pub fn gamma_shape(&self) -> Option<f64> {
+        self.gamma_shape
+    }
+
+    /// Change the rate of each site by a gamma distributed factor with shape
+    /// ``gamma_shape`` and mean one. Smaller shapes give more heterogeneity.
+    ///
+    /// # Panics
+    ///
+    /// If ``gamma_shape`` is not positive.
+    pub fn set_gamma_shape(&mut self, gamma_shape: f64) -> &mut Self {
+        assert!(gamma_shape > 0.0, "The shape of the gamma distribution must be positive.");
+        self.gamma_shape = Some(gamma_shape);
+        self
+    }
+
+    /// Evolves a sequence down every branch of ``genealogy`` and returns the
+    /// sequence of each individual.
+    pub fn sample_sequences<S>(&self, genealogy: &Genealogy, rng: &mut S) -> Vec<Vec<Nucleotide>>
+    where
+        S: Rng,
+    {
+        // Model
+
+        let rate_matrix = self.model.rate_matrix();
+        let jumps: Vec<WeightedIndex<f64>> = (0..4)
+            .map(|i| WeightedIndex::new((0..4).map(|j| if i == j { 0.0 } else { rate_matrix[i][j] })).unwrap())
+            .collect();
+        let site_rates: Vec<f64> = match self.gamma_shape {
+            Some(shape) => {
+                let gamma = Gamma::new(shape, 1.0 / shape).unwrap();
+                (0..self.sequence_length).map(|_| gamma.sample(rng)).collect()
+            },
+            None => vec![1.0; self.sequence_length],
+        };
+
+        // Sequence of the common ancestor
+
+        let stationary = WeightedIndex::new(self.model.frequencies().to_vec()).unwrap();
+        let root: Vec<Nucleotide> = (0..self.sequence_length)
+            .map(|_| Nucleotide::ALL[stationary.sample(rng)])
+            .collect();
+
+        // Evolve down the branches, ancestors first
+
+        let mut node_sequences = vec![root];
+        let mut current_nodes = vec![0; genealogy.group_size()]; // node above each individual
+        let mut branches = genealogy.branches();
+        branches.sort_by_key(|branch| std::cmp::Reverse(branch.generation()));
+        for branch in branches {
+            let parent = &node_sequences[current_nodes[branch.representative()]];
+            let duration = self.mutation_rate / 2.0 * branch.length();
+            let child: Vec<Nucleotide> = parent.iter()
+                .zip(site_rates.iter())
+                .map(|(&nucleotide, &site_rate)| evolve_site(nucleotide, duration * site_rate, &rate_matrix, &jumps, rng))
+                .collect();
+
+            node_sequences.push(child);
+            for &individual in branch.descendants() {
+                current_nodes[individual] = node_sequences.len() - 1;
+            }
+        }
+
+        // Finish
+
+        current_nodes.into_iter().map(|node| node_sequences[node].clone()).collect()
+    }
+}
+
+/// State of a site after evolving for ``duration`` units of time from ``nucleotide``.
+fn evolve_site<S>(nucleotide: Nucleotide, duration: f64, rate_matrix: &[[f64; 4]; 4], jumps: &[WeightedIndex<f64>], rng: &mut S) -> Nucleotide
+where
+    S: Rng,
+{
+    if duration <= 0.0 {
+        return nucleotide;
+    }
+    let mut state = nucleotide.index();
+    let mut time = 0.0;
+
+    loop {
+        time += Exp::new(-rate_matrix[state][state]).unwrap().sample(rng);
+        if time > duration {
+            return Nucleotide::ALL[state];
+        }
+        state = jumps[state].sample(rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Proportion of sites where two individuals, separated by a path of
+    /// length 2.0, differ.
+    fn proportion_of_differences(finite_sites: &FiniteSites, seed: u64) -> f64 {
+        let genealogy = Genealogy::from_timed_steps(2, vec![(1.0, vec![vec![0, 1]])]);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(seed);
+        let sequences = finite_sites.sample_sequences(&genealogy, &mut rng);
+
+        let differences = sequences[0].iter().zip(sequences[1].iter()).filter(|(a, b)| a != b).count();
+        differences as f64 / finite_sites.sequence_length() as f64
+    }
+
+    #[test]
+    fn jukes_cantor_distance() {
+        // θ = 0.5 gives d = 0.5 expected substitutions between the individuals
+        let finite_sites = FiniteSites::new(20_000, 0.5, SubstitutionModel::Jc69);
+        let expected = 0.75 * (1.0 - (-4.0 / 3.0 * 0.5_f64).exp());
+        assert!((proportion_of_differences(&finite_sites, 0) - expected).abs() < 0.02);
+
+        let mut gamma_sites = finite_sites.clone();
+        gamma_sites.set_gamma_shape(0.5);
+        let expected = 0.75 * (1.0 - (1.0 + 4.0 * 0.5 / (3.0 * 0.5_f64)).powf(-0.5));
+        assert!((proportion_of_differences(&gamma_sites, 1) - expected).abs() < 0.02);
+    }
+
+    /// Transition probabilities exp(Q t), by scaling and squaring of the Taylor series.
+    fn transition_matrix(rate_matrix: &[[f64; 4]; 4], time: f64) -> [[f64; 4]; 4] {
+        let squarings = 10;
+        let scale = time / 2.0_f64.powi(squarings);
+        let mut matrix = [[0.0; 4]; 4];
+        let mut term = [[0.0; 4]; 4];
+        for i in 0..4 {
+            matrix[i][i] = 1.0;
+            term[i][i] = 1.0;
+        }
+        for order in 1..20 {
+            term = product(&term, rate_matrix);
+            for (term_row, matrix_row) in term.iter_mut().zip(matrix.iter_mut()) {
+                for (entry, total) in term_row.iter_mut().zip(matrix_row.iter_mut()) {
+                    *entry *= scale / order as f64;
+                    *total += *entry;
+                }
+            }
+        }
+        for _ in 0..squarings {
+            matrix = product(&matrix, &matrix);
+        }
+        matrix
+    }
+
+    fn product(first: &[[f64; 4]; 4], second: &[[f64; 4]; 4]) -> [[f64; 4]; 4] {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = (0..4).map(|k| first[i][k] * second[k][j]).sum();
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn general_time_reversible() {
+        let frequencies = [0.1, 0.2, 0.3, 0.4];
+        let model = SubstitutionModel::Gtr { rates: [1.0, 2.0, 0.5, 1.5, 3.0, 1.0], frequencies };
+        let rate_matrix = model.rate_matrix();
+        let flows: Vec<f64> = (0..4).map(|j| (0..4).map(|i| frequencies[i] * rate_matrix[i][j]).sum()).collect();
+        assert!(flows.iter().all(|flow| flow.abs() < 1e-10));
+
+        // Individuals separated by a path of θ = 0.8 expected substitutions
+        let mutation_rate = 0.8;
+        let sequence_length = 40_000;
+        let genealogy = Genealogy::from_timed_steps(2, vec![(1.0, vec![vec![0, 1]])]);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let sequences = FiniteSites::new(sequence_length, mutation_rate, model).sample_sequences(&genealogy, &mut rng);
+
+        // Joint frequency of each pair of nucleotides, π_i P_ij(θ) by reversibility
+        let transitions = transition_matrix(&rate_matrix, mutation_rate);
+        for first in Nucleotide::ALL.iter() {
+            for second in Nucleotide::ALL.iter() {
+                let count = sequences[0].iter()
+                    .zip(sequences[1].iter())
+                    .filter(|&(a, b)| a == first && b == second)
+                    .count();
+                let expected = frequencies[first.index()] * transitions[first.index()][second.index()];
+                assert!((count as f64 / sequence_length as f64 - expected).abs() < 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Selective sweep.
+//!
+//! Genealogy at a neutral locus linked to a beneficial allele that swept
+//! through the population. Going backwards in time, lineages are in the
+//! background of the beneficial allele or of the wild type, as in a structured
+//! coalescent whose deme sizes follow the frequency x of the beneficial allele:
+//! pairs coalesce at rate 1 / x in the beneficial background and 1 / (1 - x)
+//! in the wild type one, while recombination moves a lineage to the other
+//! background at rate ρ times the frequency of the other background.
+//!
+//! Lineages are sampled when the sweep ends, all in the beneficial background.
+//! When the beneficial allele appears, all its lineages merge at once, and the
+//! remaining lineages follow a standard ``Coalescent``.
+//!
+
+// Types
+use rand_distr::{Binomial, Exp};
+use rand::distributions::WeightedIndex;
+use crate::{Coalescent, Genealogy, Step};
+
+// Traits
+use rand::distributions::Distribution;
+use rand::Rng;
+use core::fmt::Debug;
+
+/// Number of intervals of a logistic trajectory.
+const LOGISTIC_INTERVALS: usize = 1000;
+
+/// Frequency of the beneficial allele during a sweep, going backwards in time
+/// from the end of the sweep. The frequency is constant in consecutive
+/// intervals of time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    durations: Vec<f64>,
+    frequencies: Vec<f64>,
+}
+
+impl Trajectory {
+    /// Creates a new Trajectory where the frequency is ``frequencies[i]``
+    /// during the i-th interval, of length ``durations[i]``, going backwards
+    /// in time.
+    ///
+    /// # Panics
+    ///
+    /// If there is not one frequency per interval, some duration is negative
+    /// or some frequency is not in (0, 1].
+    ///
+    /// # Remarks
+    ///
+    /// During intervals of frequency one, lineages in the wild type
+    /// background do not coalesce until the sweep ends.
+    pub fn new(durations: Vec<f64>, frequencies: Vec<f64>) -> Self {
+        assert_eq!(durations.len(), frequencies.len(), "There must be one frequency per interval.");
+        assert!(durations.iter().all(|&duration| duration >= 0.0), "Durations must be non-negative.");
+        assert!(frequencies.iter().all(|&frequency| 0.0 < frequency && frequency <= 1.0), "Frequencies must be in (0, 1].");
+
+        Trajectory { durations, frequencies }
+    }
+
+    /// Deterministic trajectory of a beneficial allele with scaled selection
+    /// strength α, following the logistic equation dx/dt = α x (1 - x) from
+    /// frequency ``initial_frequency`` to ``1 - initial_frequency``.
+    ///
+    /// # Panics
+    ///
+    /// If ``selection_strength`` is not positive or ``initial_frequency`` is
+    /// not in (0, 1/2).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let trajectory = coalescence::Trajectory::logistic(1000.0, 0.001);
+    ///
+    /// // The sweep takes about 2 ln(1 / ε) / α.
+    /// assert!((trajectory.duration() - 2.0 * (999.0_f64).ln() / 1000.0).abs() < 1e-10);
+    /// ```
+    pub fn logistic(selection_strength: f64, initial_frequency: f64) -> Self {
+        assert!(selection_strength > 0.0, "The selection strength must be positive.");
+        assert!(0.0 < initial_frequency && initial_frequency < 0.5, "The initial frequency must be in (0, 1/2).");
+
+        let ratio = initial_frequency / (1.0 - initial_frequency);
+        let total_duration = -2.0 * ratio.ln() / selection_strength;
+        let duration = total_duration / LOGISTIC_INTERVALS as f64;
+        let frequencies = (0..LOGISTIC_INTERVALS)
+            .map(|i| {
+                let time = (i as f64 + 0.5) * duration;
+                1.0 / (1.0 + ratio * (selection_strength * time).exp())
+            })
+            .collect();
+
+        Trajectory::new(vec![duration; LOGISTIC_INTERVALS], frequencies)
+    }
+
+    /// Random trajectory of a beneficial allele in a Wright-Fisher population
+    /// of ``population_size`` haploid individuals, with selection coefficient
+    /// ``selection_coefficient``, from a single copy until fixation. Each
+    /// generation lasts 1 / ``population_size``.
+    ///
+    /// # Remarks
+    ///
+    /// Trajectories where the allele is lost are discarded.
+    ///
+    /// # Panics
+    ///
+    /// If ``population_size`` is zero or ``selection_coefficient`` is not positive.
+    pub fn wright_fisher<S>(population_size: u64, selection_coefficient: f64, rng: &mut S) -> Self
+    where
+        S: Rng,
+    {
+        assert!(population_size > 0, "The population size must be positive.");
+        assert!(selection_coefficient > 0.0, "The selection coefficient must be positive.");
+
+        let mut counts = Vec::new();
+        while counts.last() != Some(&population_size) {
+            counts = vec![1];
+            let mut count = 1;
+            while 0 < count && count < population_size {
+                let frequency = count as f64 / population_size as f64;
+                let selected = frequency * (1.0 + selection_coefficient) / (1.0 + frequency * selection_coefficient);
+                count = Binomial::new(population_size, selected).unwrap().sample(rng);
+                counts.push(count);
+            }
+        }
+
+        // Backwards in time, starting at fixation
+        let frequencies = counts[..counts.len() - 1]
+            .iter()
+            .rev()
+            .map(|&count| count as f64 / population_size as f64)
+            .collect::<Vec<f64>>();
+        let durations = vec![1.0 / population_size as f64; frequencies.len()];
+
+        Trajectory::new(durations, frequencies)
+    }
+
+    /// Length of each interval of time.
+    pub fn durations(&self) -> &[f64] {
+        &self.durations
+    }
+
+    /// Frequency of the beneficial allele in each interval of time.
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    /// Total duration of the sweep.
+    pub fn duration(&self) -> f64 {
+        self.durations.iter().sum()
+    }
+}
+
+/// Selective sweep of a beneficial allele, with a group of individuals
+/// sampled at a linked neutral locus when the sweep ends.
+///
+/// Time is measured in coalescent units, and ``recombination_rate`` is the
+/// scaled recombination rate ρ between both loci.
+///
+/// # Examples
+///
+/// A strong sweep produces star-like genealogies.
+/// ```
+/// use coalescence::{SelectiveSweep, Trajectory};
+///
+/// let group_size = 20;
+/// let sweep = SelectiveSweep::new(group_size, 1.0, Trajectory::logistic(1000.0, 0.001));
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = sweep.sample_genealogy(&mut rng);
+/// let neutral_genealogy = coalescence::Coalescent::new(group_size, rng.clone()).sample_genealogy(&mut rng);
+///
+/// let divergences = (genealogy.mean_pairwise_divergence(), neutral_genealogy.mean_pairwise_divergence());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectiveSweep {
+    group_size: usize,
+    recombination_rate: f64,
+    trajectory: Trajectory,
+}
+
+impl SelectiveSweep {
+    /// Creates a new SelectiveSweep.
+    ///
+    /// # Panics
+    ///
+    /// If ``recombination_rate`` is negative.
+    pub fn new(group_size: usize, recombination_rate: f64, trajectory: Trajectory) -> Self {
+        assert!(recombination_rate >= 0.0, "The recombination rate must be non-negative.");
+
+        SelectiveSweep { group_size, recombination_rate, trajectory }
+    }
+
+    /// Number of individuals in the group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Scaled recombination rate between the selected and the neutral locus.
+    pub fn recombination_rate(&self) -> f64 {
+        self.recombination_rate
+    }
+
+    /// Frequency of the beneficial allele during the sweep.
+    pub fn trajectory(&self) -> &Trajectory {
+        &self.trajectory
+    }
+
+    /// Sample the genealogy of the group at the neutral locus.
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Lineages, represented by an individual, in each background
+
+        let mut beneficial: Vec<usize> = (0..self.group_size).collect();
+        let mut wild: Vec<usize> = Vec::new();
+        let mut timed_steps: Vec<(f64, Step)> = Vec::new();
+        let mut start = 0.0;
+
+        // Structured coalescent during the sweep
+
+        for (&duration, &frequency) in self.trajectory.durations.iter().zip(&self.trajectory.frequencies) {
+            let end = start + duration;
+            let mut time = start;
+            loop {
+                let (k_beneficial, k_wild) = (beneficial.len(), wild.len());
+                let rates = [
+                    if k_beneficial > 1 { (k_beneficial * (k_beneficial - 1) / 2) as f64 / frequency } else { 0.0 },
+                    // Without wild type individuals, its lineages can not coalesce
+                    if k_wild > 1 && frequency < 1.0 { (k_wild * (k_wild - 1) / 2) as f64 / (1.0 - frequency) } else { 0.0 },
+                    k_beneficial as f64 * self.recombination_rate * (1.0 - frequency),
+                    k_wild as f64 * self.recombination_rate * frequency,
+                ];
+                let total_rate: f64 = rates.iter().sum();
+                if total_rate <= 0.0 {
+                    break;
+                }
+                time += Exp::new(total_rate).unwrap().sample(rng);
+                if time >= end {
+                    break;
+                }
+
+                match WeightedIndex::new(rates).unwrap().sample(rng) {
+                    0 => timed_steps.push((time, coalesce(&mut beneficial, rng))),
+                    1 => timed_steps.push((time, coalesce(&mut wild, rng))),
+                    2 => wild.push(beneficial.swap_remove(rng.gen_range(0, k_beneficial))),
+                    _ => beneficial.push(wild.swap_remove(rng.gen_range(0, k_wild))),
+                }
+            }
+            start = end;
+        }
+
+        // Origin of the beneficial allele
+
+        if beneficial.len() > 1 {
+            timed_steps.push((start, vec![beneficial.clone()]));
+        }
+        let mut lineages = wild;
+        lineages.extend(beneficial.into_iter().min());
+
+        // Standard coalescent before the sweep
+
+        if lineages.len() > 1 {
+            let mut coalescent_process = Coalescent::new(lineages.len(), rng.clone());
+            while let Some((_, step)) = coalescent_process.next_step() {
+                let step: Step = step
+                    .iter()
+                    .map(|merger| merger.iter().map(|&i| lineages[i]).collect())
+                    .collect();
+                timed_steps.push((start + coalescent_process.time(), step));
+            }
+            *rng = coalescent_process.rng().clone();
+        }
+
+        // Finish
+
+        Genealogy::from_timed_steps(self.group_size, timed_steps)
+    }
+}
+
+/// Merges two uniformly chosen lineages among ``lineages``, returning the step.
+fn coalesce<S: Rng>(lineages: &mut Vec<usize>, rng: &mut S) -> Step {
+    let pair = rand::seq::index::sample(rng, lineages.len(), 2);
+    let (first, second) = (pair.index(0).min(pair.index(1)), pair.index(0).max(pair.index(1)));
+    let step = vec![vec![lineages[first], lineages[second]]];
+    lineages.swap_remove(second);
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn wright_fisher_trajectory_fixes() {
+        let trajectory = Trajectory::wright_fisher(100, 0.2, &mut rand_pcg::Pcg32::seed_from_u64(0));
+
+        assert!(trajectory.frequencies().iter().all(|&frequency| frequency < 1.0));
+        assert_eq!(*trajectory.frequencies().last().unwrap(), 0.01);
+        assert!((trajectory.duration() - trajectory.frequencies().len() as f64 / 100.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn sweeps_without_recombination_are_stars() {
+        let group_size = 10;
+        let trajectory = Trajectory::logistic(100.0, 0.01);
+        let duration = trajectory.duration();
+        let sweep = SelectiveSweep::new(group_size, 0.0, trajectory);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+
+        for _ in 0..10 {
+            let genealogy = sweep.sample_genealogy(&mut rng);
+            assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+            assert!(genealogy.depth() <= duration + 1e-10);
+        }
+    }
+
+    #[test]
+    fn recombination_restores_diversity() {
+        let group_size = 10;
+        let samples = 200;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(2);
+        let mut mean_divergence = |recombination_rate: f64| {
+            let sweep = SelectiveSweep::new(group_size, recombination_rate, Trajectory::logistic(100.0, 0.01));
+            (0..samples)
+                .map(|_| sweep.sample_genealogy(&mut rng).mean_pairwise_divergence())
+                .sum::<f64>() / samples as f64
+        };
+
+        assert!(mean_divergence(0.0) < 0.2);
+        assert!(mean_divergence(1e4) > 1.5);
+    }
+
+    #[test]
+    fn fixed_intervals_stop_wild_type_coalescence() {
+        let trajectory = Trajectory::new(vec![1.0, 1.0], vec![0.5, 1.0]);
+        let sweep = SelectiveSweep::new(10, 10.0, trajectory);
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+
+        for _ in 0..20 {
+            let genealogy = sweep.sample_genealogy(&mut rng);
+            assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+            assert!(genealogy.time_steps().iter().all(|time_step| time_step.is_finite()));
+        }
+    }
+}
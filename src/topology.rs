@@ -0,0 +1,247 @@
+//! Migration topologies of structured populations.
+//!
+//! Ready-made migration matrices for the most common shapes of population
+//! structure, to be used by a ``StructuredCoalescent``. In all of them, every
+//! lineage leaves its deme at the same total rate, split equally among the
+//! neighbouring demes.
+//!
+
+/// Shape of the connections between demes.
+///
+/// # Examples
+///
+/// Mean divergence of pairs as a function of the distance between their demes.
+/// ```
+/// use coalescence::{StructuredCoalescent, Topology};
+///
+/// let topology = Topology::SteppingStone { demes: 4, circular: false };
+/// let rng = rand::thread_rng();
+/// let coalescent = StructuredCoalescent::with_topology(&[5; 4], &topology, 1.0, rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+/// for deme in 0..topology.num_demes() {
+///     let distance = topology.distance(0, deme);
+///     let divergence = genealogy.mean_divergence_between(0, deme);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Topology {
+    /// Fully symmetric island model: every deme is connected to all the others.
+    Island {
+        /// Number of demes.
+        demes: usize,
+    },
+    /// One dimensional stepping-stone model: demes in a line, connected to
+    /// their immediate neighbours. If circular, the ends of the line are
+    /// also connected.
+    SteppingStone {
+        /// Number of demes.
+        demes: usize,
+        /// Whether the first and last demes are neighbours.
+        circular: bool,
+    },
+    /// Two dimensional stepping-stone model on a torus: demes in a grid,
+    /// connected to their four immediate neighbours, wrapping around the edges.
+    /// The deme in row r and column c has index r * columns + c. Both
+    /// dimensions must be positive, see ``Topology::torus``; otherwise the
+    /// torus has no demes.
+    Torus {
+        /// Number of rows of the grid.
+        rows: usize,
+        /// Number of columns of the grid.
+        columns: usize,
+    },
+}
+
+impl Topology {
+    /// Creates a new two dimensional stepping-stone model on a torus.
+    ///
+    /// # Panics
+    ///
+    /// If ``rows`` or ``columns`` are zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::Topology;
+    ///
+    /// let topology = Topology::torus(3, 4);
+    ///
+    /// assert_eq!(topology.num_demes(), 12);
+    /// assert_eq!(topology.neighbours(0), vec![8, 4, 3, 1]);
+    /// ```
+    pub fn torus(rows: usize, columns: usize) -> Self {
+        assert!(rows > 0 && columns > 0, "A torus must have at least one row and one column.");
+
+        Topology::Torus { rows, columns }
+    }
+
+    /// Total number of demes.
+    pub fn num_demes(&self) -> usize {
+        match *self {
+            Topology::Island { demes } => demes,
+            Topology::SteppingStone { demes, .. } => demes,
+            Topology::Torus { rows, columns } => rows * columns,
+        }
+    }
+
+    /// Neighbours of a deme, with repetitions if a deme is a neighbour
+    /// in more than one direction.
+    ///
+    /// # Panics
+    ///
+    /// If ``deme`` is not in the topology.
+    pub fn neighbours(&self, deme: usize) -> Vec<usize> {
+        assert!(deme < self.num_demes(), "The deme is not in the topology.");
+
+        match *self {
+            Topology::Island { demes } => (0..demes).filter(|&other| other != deme).collect(),
+            Topology::SteppingStone { demes, circular } => {
+                let mut neighbours = Vec::with_capacity(2);
+                if deme > 0 {
+                    neighbours.push(deme - 1);
+                } else if circular && demes > 1 {
+                    neighbours.push(demes - 1);
+                }
+                if deme + 1 < demes {
+                    neighbours.push(deme + 1);
+                } else if circular && demes > 1 {
+                    neighbours.push(0);
+                }
+                neighbours
+            },
+            Topology::Torus { rows, columns } => {
+                let (row, column) = (deme / columns, deme % columns);
+                vec![
+                    ((row + rows - 1) % rows) * columns + column,
+                    ((row + 1) % rows) * columns + column,
+                    row * columns + (column + columns - 1) % columns,
+                    row * columns + (column + 1) % columns,
+                ]
+                .into_iter()
+                .filter(|&other| other != deme)
+                .collect()
+            },
+        }
+    }
+
+    /// Migration matrix where each lineage leaves its deme at rate
+    /// ``migration_rate``, going to each neighbour with the same probability.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::Topology;
+    ///
+    /// let topology = Topology::SteppingStone { demes: 3, circular: false };
+    /// let migration_matrix = topology.migration_matrix(1.0);
+    ///
+    /// assert_eq!(migration_matrix[0], vec![0.0, 1.0, 0.0]);
+    /// assert_eq!(migration_matrix[1], vec![0.5, 0.0, 0.5]);
+    /// ```
+    pub fn migration_matrix(&self, migration_rate: f64) -> Vec<Vec<f64>> {
+        let num_demes = self.num_demes();
+        let mut migration_matrix = vec![vec![0.0; num_demes]; num_demes];
+
+        for (deme, row) in migration_matrix.iter_mut().enumerate() {
+            let neighbours = self.neighbours(deme);
+            for &neighbour in &neighbours {
+                row[neighbour] += migration_rate / neighbours.len() as f64;
+            }
+        }
+
+        migration_matrix
+    }
+
+    /// Minimum number of migrations needed to go from one deme to another.
+    ///
+    /// # Panics
+    ///
+    /// If either deme is not in the topology.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use coalescence::Topology;
+    ///
+    /// let topology = Topology::torus(4, 5);
+    ///
+    /// assert_eq!(topology.distance(0, 0), 0);
+    /// assert_eq!(topology.distance(0, 4), 1);
+    /// assert_eq!(topology.distance(0, 12), 4);
+    /// ```
+    pub fn distance(&self, deme_1: usize, deme_2: usize) -> usize {
+        assert!(deme_1.max(deme_2) < self.num_demes(), "The deme is not in the topology.");
+
+        let cyclic_distance = |a: usize, b: usize, length: usize| {
+            let difference = a.max(b) - a.min(b);
+            difference.min(length - difference)
+        };
+
+        match *self {
+            Topology::Island { .. } => (deme_1 != deme_2) as usize,
+            Topology::SteppingStone { demes, circular } => match circular {
+                true => cyclic_distance(deme_1, deme_2, demes),
+                false => deme_1.max(deme_2) - deme_1.min(deme_2),
+            },
+            Topology::Torus { rows, columns } => {
+                cyclic_distance(deme_1 / columns, deme_2 / columns, rows)
+                    + cyclic_distance(deme_1 % columns, deme_2 % columns, columns)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_sum_to_migration_rate() {
+        let topologies = vec![
+            Topology::Island { demes: 5 },
+            Topology::SteppingStone { demes: 5, circular: true },
+            Topology::SteppingStone { demes: 5, circular: false },
+            Topology::torus(3, 4),
+            Topology::torus(2, 2),
+        ];
+        for topology in topologies {
+            for row in topology.migration_matrix(2.0) {
+                assert!((row.iter().sum::<f64>() - 2.0).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn distances() {
+        let circle = Topology::SteppingStone { demes: 6, circular: true };
+        assert_eq!(circle.distance(0, 5), 1);
+        assert_eq!(circle.distance(1, 4), 3);
+
+        let line = Topology::SteppingStone { demes: 6, circular: false };
+        assert_eq!(line.distance(0, 5), 5);
+
+        let island = Topology::Island { demes: 6 };
+        assert_eq!(island.distance(0, 5), 1);
+        assert_eq!(island.distance(3, 3), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one row")]
+    fn empty_torus() {
+        Topology::torus(0, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "not in the topology")]
+    fn distance_outside_circle() {
+        Topology::SteppingStone { demes: 4, circular: true }.distance(0, 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "not in the topology")]
+    fn neighbours_of_empty_torus() {
+        Topology::Torus { rows: 0, columns: 3 }.neighbours(0);
+    }
+}
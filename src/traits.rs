@@ -1 +1,48 @@
+//! Traits to define your own coalescent processes.
 
+/// Finite measure Λ on [0, 1], defining a Λ-coalescent.
+///
+/// When there are b sets in the partition, any particular group of k of them
+/// merges into one set at rate λ_{b,k} = ∫ x^{k-2} (1-x)^{b-k} Λ(dx).
+///
+/// Closures ``Fn(usize, usize) -> f64`` computing λ_{b,k} are measures.
+///
+/// # Examples
+///
+/// The standard coalescent as a Λ-coalescent, with Λ the Dirac measure at zero.
+/// ```
+/// use coalescence::traits::LambdaMeasure;
+///
+/// let kingman = |_blocks: usize, merging: usize| (merging == 2) as usize as f64;
+/// let rates = kingman.merger_rates(4);
+///
+/// assert!((rates[0] - 6.0).abs() < 1e-10);
+/// assert_eq!(rates[1..], [0.0, 0.0]);
+/// ```
+pub trait LambdaMeasure {
+    /// Rate λ_{b,k} at which any particular group of ``merging`` sets out of
+    /// ``blocks`` sets merges.
+    fn merger_rate(&self, blocks: usize, merging: usize) -> f64;
+
+    /// Total rate at which some group of k sets merges, for k = 2, ..., ``blocks``.
+    /// The i-th element corresponds to k = i + 2.
+    ///
+    /// # Remarks
+    ///
+    /// The default implementation multiplies each λ_{b,k} by the binomial
+    /// coefficient, which can be numerically unstable for large ``blocks``.
+    fn merger_rates(&self, blocks: usize) -> Vec<f64> {
+        (2..=blocks)
+            .map(|merging| crate::lambda::ln_binomial(blocks, merging).exp() * self.merger_rate(blocks, merging))
+            .collect()
+    }
+}
+
+impl<F> LambdaMeasure for F
+where
+    F: Fn(usize, usize) -> f64,
+{
+    fn merger_rate(&self, blocks: usize, merging: usize) -> f64 {
+        self(blocks, merging)
+    }
+}
@@ -0,0 +1,504 @@
+//! Wright-Fisher model.
+//!
+//! Forward in time simulation of a population of N haploid individuals with
+//! discrete generations: each individual of a generation picks its parent
+//! uniformly at random among the individuals of the previous generation.
+//! Parent links are recorded, so that the genealogy of any group of the
+//! current generation can be recovered.
+//!
+//! Measuring time in units of N generations, genealogies converge to the ones
+//! of the standard ``Coalescent`` as N grows. For small populations and large
+//! groups, the exact genealogies can instead be simulated backwards in time,
+//! generation by generation, with several lineages possibly finding the same
+//! parent and several such mergers in the same generation.
+//!
+
+// Types
+use partitions::PartitionVec;
+use std::collections::{HashMap, VecDeque};
+use crate::{Genealogy, Step};
+use crate::genealogy::apply_step;
+
+// Traits
+use markovian::traits::CMarkovChainTrait;
+use rand::Rng;
+use core::fmt::Debug;
+use std::iter::FromIterator;
+
+/// Wright-Fisher population of haploid individuals, evolving forward in time.
+///
+/// It has a random number generator associated, R, to choose the parents.
+///
+/// # Examples
+///
+/// ```
+/// let population_size = 100;
+/// let rng = rand::thread_rng();
+/// let mut population = coalescence::WrightFisher::new(population_size, rng);
+/// population.evolve(20 * population_size);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = population.sample_genealogy(10, &mut rng).expect("The group has no common ancestor yet.");
+///
+/// // In coalescent units
+/// let depth = genealogy.depth() / population_size as f64;
+/// ```
+#[derive(Debug, Clone)]
+pub struct WrightFisher<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    population_size: usize,
+    generation: usize,
+    parents: VecDeque<Vec<usize>>, // of each generation, from the oldest recorded one
+    discard_threshold: usize, // recorded generations before trying to discard old links
+    rng: R,
+}
+
+impl<R> WrightFisher<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new WrightFisher population without history.
+    ///
+    /// # Panics
+    ///
+    /// If ``population_size`` is zero.
+    pub fn new(population_size: usize, rng: R) -> Self {
+        assert!(population_size > 0, "The population size must be positive.");
+
+        WrightFisher { population_size, generation: 0, parents: VecDeque::new(), discard_threshold: 4 * population_size, rng }
+    }
+
+    /// Number of individuals in each generation.
+    pub fn population_size(&self) -> usize {
+        self.population_size
+    }
+
+    /// Number of generations simulated.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Number of past generations whose parent links are recorded.
+    ///
+    /// # Remarks
+    ///
+    /// Links older than the common ancestor of the current generation are
+    /// discarded, since they are not part of any genealogy. This is attempted
+    /// once 4N generations are recorded; if the common ancestor is older, the
+    /// number of generations before the next attempt is doubled.
+    pub fn recorded_generations(&self) -> usize {
+        self.parents.len()
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Produces the next generation, each individual choosing its parent
+    /// uniformly at random.
+    pub fn next_generation(&mut self) -> &mut Self {
+        let parents = (0..self.population_size)
+            .map(|_| self.rng.gen_range(0, self.population_size))
+            .collect();
+        self.parents.push_back(parents);
+        self.generation += 1;
+
+        if self.parents.len() > self.discard_threshold {
+            self.discard_threshold = match self.discard_old_links() {
+                true if self.parents.len() < 4 * self.population_size => 4 * self.population_size,
+                true => 2 * self.parents.len(),
+                false => 2 * self.discard_threshold,
+            };
+        }
+        self
+    }
+
+    /// Produces the next ``generations`` generations.
+    pub fn evolve(&mut self, generations: usize) -> &mut Self {
+        for _ in 0..generations {
+            self.next_generation();
+        }
+        self
+    }
+
+    /// Genealogy of the given individuals of the current generation, with
+    /// time measured in generations. Individual i of the genealogy is
+    /// ``individuals[i]``.
+    ///
+    /// Returns ``None`` if the individuals have no common ancestor in the
+    /// recorded history.
+    ///
+    /// # Panics
+    ///
+    /// If some individual is not in the population.
+    pub fn genealogy(&self, individuals: &[usize]) -> Option<Genealogy> {
+        assert!(individuals.iter().all(|&individual| individual < self.population_size), "The individual is not in the population.");
+
+        let mut lineages: Vec<(usize, usize)> = individuals.iter().cloned().zip(0..).collect();
+        let mut timed_steps: Vec<(f64, Step)> = Vec::new();
+
+        for (generations_back, parents) in self.parents.iter().rev().enumerate() {
+            if lineages.len() <= 1 {
+                break;
+            }
+            let step = parent_step(&mut lineages, |ancestor| parents[ancestor]);
+            if !step.is_empty() {
+                timed_steps.push(((generations_back + 1) as f64, step));
+            }
+        }
+
+        match lineages.len() <= 1 {
+            true => Some(Genealogy::from_timed_steps(individuals.len(), timed_steps)),
+            false => None,
+        }
+    }
+
+    /// Genealogy of ``sample_size`` different individuals of the current
+    /// generation, chosen uniformly at random. See ``genealogy`` method.
+    ///
+    /// # Panics
+    ///
+    /// If ``sample_size`` is larger than the population size.
+    pub fn sample_genealogy<S>(&self, sample_size: usize, rng: &mut S) -> Option<Genealogy>
+    where
+        S: Rng,
+    {
+        let individuals = rand::seq::index::sample(rng, self.population_size, sample_size).into_vec();
+        self.genealogy(&individuals)
+    }
+
+    /// Discards the parent links older than the common ancestor of the
+    /// current generation. Returns whether the common ancestor was found.
+    fn discard_old_links(&mut self) -> bool {
+        let mut ancestors: Vec<usize> = (0..self.population_size).collect();
+        for (generations_back, parents) in self.parents.iter().rev().enumerate() {
+            ancestors = ancestors.into_iter().map(|ancestor| parents[ancestor]).collect();
+            ancestors.sort_unstable();
+            ancestors.dedup();
+            if ancestors.len() == 1 {
+                let needed = generations_back + 1;
+                let discarded = self.parents.len() - needed;
+                self.parents.drain(..discarded);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Discrete-time Wright-Fisher coalescent, the exact genealogy of a group of
+/// a Wright-Fisher population of ``population_size`` haploid individuals,
+/// simulated backwards in time. Each generation, every lineage picks its
+/// parent uniformly at random, and lineages with the same parent merge.
+///
+/// Time is measured in generations, and generations without mergers are
+/// skipped.
+///
+/// # Examples
+///
+/// A large group in a small population has multiple and simultaneous mergers.
+/// ```
+/// let rng = rand::thread_rng();
+/// let coalescent = coalescence::WrightFisherCoalescent::new(100, 50, rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+///
+/// assert!(genealogy.time_steps().iter().all(|time_step| time_step.fract() == 0.0));
+/// assert!(genealogy.steps().len() < 99);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WrightFisherCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    state: PartitionVec<()>,
+    population_size: usize,
+    time: f64,
+    rng: R,
+}
+
+impl<R> WrightFisherCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new WrightFisherCoalescent.
+    ///
+    /// # Panics
+    ///
+    /// If ``population_size`` is zero.
+    pub fn new(group_size: usize, population_size: usize, rng: R) -> Self {
+        assert!(population_size > 0, "The population size must be positive.");
+        let state: PartitionVec<()> =
+            PartitionVec::from_iter((0..group_size).map(|_| ()));
+
+        WrightFisherCoalescent { state, population_size, time: 0.0, rng }
+    }
+
+    /// Current time of the process, in generations.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Number of individuals in each generation.
+    pub fn population_size(&self) -> usize {
+        self.population_size
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Peeks a possible next step, i.e. the mergers of the next generation
+    /// where some lineages find the same parent, together with the number of
+    /// generations until then. This does not change the state of the
+    /// ``WrightFisherCoalescent``.
+    pub fn peek_next_step(&mut self) -> Option<(f64, Step)> {
+        let representatives: Vec<usize> = self.state
+            .all_sets()
+            .map(|mut set| set.next().unwrap().0)
+            .collect();
+        let k = representatives.len();
+
+        if k <= 1 {
+            None
+        } else {
+            let population_size = self.population_size as f64;
+
+            // Probability that the first repeated parent is the one of lineage j
+
+            let mut all_different = 1.0;
+            let first_repetition: Vec<f64> = (0..k)
+                .map(|j| {
+                    let probability = all_different * j.min(self.population_size) as f64 / population_size;
+                    all_different *= 1.0 - j as f64 / population_size;
+                    probability
+                })
+                .collect();
+            let merger_probability: f64 = first_repetition.iter().sum();
+
+            // Simulate generations without mergers
+
+            let generations = match merger_probability < 1.0 {
+                true => ((1.0 - self.rng.gen::<f64>()).ln() / (-merger_probability).ln_1p()).floor(),
+                false => 0.0,
+            };
+
+            // Choose parents, given that some of them are repeated
+
+            let mut remaining = self.rng.gen::<f64>() * merger_probability;
+            let first_repeated = first_repetition
+                .iter()
+                .position(|&probability| {
+                    remaining -= probability;
+                    remaining < 0.0
+                })
+                .unwrap_or(k - 1)
+                .max(1);
+            let mut parents = rand::seq::index::sample(&mut self.rng, self.population_size, first_repeated).into_vec();
+            parents.push(parents[self.rng.gen_range(0, first_repeated)]);
+            for _ in (first_repeated + 1)..k {
+                parents.push(self.rng.gen_range(0, self.population_size));
+            }
+
+            // Return
+
+            let mut lineages: Vec<(usize, usize)> = representatives.into_iter().enumerate().collect();
+            let step = parent_step(&mut lineages, |lineage| parents[lineage]);
+            Some((generations + 1.0, step))
+        }
+    }
+
+    /// Changes to a next state of the ``WrightFisherCoalescent``, chosen
+    /// according to the stochastic process and returning the indexes of
+    /// elements that represent the sets of the partitions that were joint
+    /// to produce this next state.
+    pub fn next_step(&mut self) -> Option<(f64, Step)> {
+        match self.peek_next_step() {
+            Some((time_step, step)) => {
+                apply_step(&mut self.state, &step);
+                self.time += time_step;
+                Some((time_step, step))
+            },
+            None => None,
+        }
+    }
+
+    /// Sample a genealogy: from the initial partition of singletons until there
+    /// is only one set in the partition. Returns a ``Genealogy`` where
+    /// postprocess is possible.
+    ///
+    /// # Remarks
+    ///
+    /// No internal state changes, including the internal
+    /// random number generator. This is why this methods requires a rng.
+    ///
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Initialize a WrightFisherCoalescent
+
+        let group_size = self.state.len();
+        let mut coalescent_process = WrightFisherCoalescent::new(group_size, self.population_size, rng.clone());
+
+        // Generate transitions
+
+        let mut timed_steps = Vec::new();
+        while let Some((_, step)) = coalescent_process.next_step() {
+            timed_steps.push((coalescent_process.time(), step));
+        }
+
+        // Update rng
+
+        *rng = coalescent_process.rng.clone();
+
+        // Finish
+
+        Genealogy::from_timed_steps(group_size, timed_steps)
+    }
+}
+
+impl<R> CMarkovChainTrait<PartitionVec<()>> for WrightFisherCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Current state of the process.
+    fn state(&self) -> &PartitionVec<()> {
+        &self.state
+    }
+
+    /// Change the current state of the process.
+    fn set_state(&mut self, state: PartitionVec<()>) -> &mut Self {
+        self.state = state;
+        self
+    }
+}
+
+impl<R> Iterator for WrightFisherCoalescent<R>
+where
+    R: Rng + Clone + core::fmt::Debug,
+{
+    type Item = (f64, PartitionVec<()>);
+
+    /// Changes the state of the ``WrightFisherCoalescent`` to a new state,
+    /// chosen according to the stochastic process.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_step() {
+            Some((time_step, _)) => Some((time_step, self.state.clone())),
+            None => None,
+        }
+    }
+}
+
+/// Moves each lineage, given by its current ancestor and a representative
+/// individual, to the parent of its ancestor. Returns the mergers of lineages
+/// that find the same parent.
+pub(crate) fn parent_step<F>(lineages: &mut Vec<(usize, usize)>, parent: F) -> Step
+where
+    F: Fn(usize) -> usize,
+{
+    let mut by_parent: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &(ancestor, representative) in lineages.iter() {
+        by_parent.entry(parent(ancestor)).or_default().push(representative);
+    }
+
+    let mut step: Step = by_parent
+        .values()
+        .filter(|representatives| representatives.len() > 1)
+        .cloned()
+        .collect();
+    step.sort();
+
+    *lineages = by_parent
+        .into_iter()
+        .map(|(ancestor, representatives)| (ancestor, *representatives.iter().min().unwrap()))
+        .collect();
+    lineages.sort_unstable();
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn old_links_are_discarded() {
+        let population_size = 20;
+        let mut population = WrightFisher::new(population_size, rand_pcg::Pcg32::seed_from_u64(0));
+        population.evolve(50 * population_size);
+
+        assert_eq!(population.generation(), 50 * population_size);
+        assert!(population.recorded_generations() <= 4 * population_size);
+
+        let everyone: Vec<usize> = (0..population_size).collect();
+        let genealogy = population.genealogy(&everyone).unwrap();
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+        assert!(genealogy.time_steps().iter().all(|time_step| time_step.fract() == 0.0));
+    }
+
+    #[test]
+    fn discarding_backs_off_without_common_ancestor() {
+        let population_size = 20;
+        let mut population = WrightFisher::new(population_size, rand_pcg::Pcg32::seed_from_u64(3));
+        let identity: Vec<usize> = (0..population_size).collect();
+        population.parents.extend(vec![identity; 4 * population_size]);
+
+        population.next_generation();
+        assert_eq!(population.recorded_generations(), 4 * population_size + 1);
+        assert_eq!(population.discard_threshold, 8 * population_size);
+
+        population.evolve(20 * population_size);
+        assert!(population.recorded_generations() <= 4 * population_size);
+        assert_eq!(population.discard_threshold, 4 * population_size);
+    }
+
+    #[test]
+    fn pairs_coalesce_after_population_size_generations() {
+        let population_size = 50;
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(1);
+
+        let samples = 400;
+        let mean_depth = (0..samples)
+            .map(|seed| {
+                let mut population = WrightFisher::new(population_size, rand_pcg::Pcg32::seed_from_u64(seed));
+                population.evolve(10 * population_size);
+                population.sample_genealogy(2, &mut rng).unwrap().depth()
+            })
+            .sum::<f64>() / samples as f64;
+
+        assert!((mean_depth / population_size as f64 - 1.0).abs() < 0.15);
+    }
+
+    #[test]
+    fn backward_simulation_matches_forward_one() {
+        // Pairs coalesce after a geometric number of generations, with mean N.
+        let population_size = 20;
+        let coalescent = WrightFisherCoalescent::new(2, population_size, rand_pcg::Pcg32::seed_from_u64(0));
+        let mut rng = rand_pcg::Pcg32::seed_from_u64(3);
+
+        let samples = 4000;
+        let mean_depth = (0..samples)
+            .map(|_| coalescent.sample_genealogy(&mut rng).depth())
+            .sum::<f64>() / samples as f64;
+        assert!((mean_depth / population_size as f64 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn groups_larger_than_the_population() {
+        let group_size = 30;
+        let coalescent = WrightFisherCoalescent::new(group_size, 10, rand_pcg::Pcg32::seed_from_u64(0));
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(4));
+
+        assert!(genealogy.steps()[0].iter().map(|merger| merger.len() - 1).sum::<usize>() >= 20);
+        assert_eq!(genealogy.time_steps()[0], 1.0);
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+    }
+}
@@ -0,0 +1,320 @@
+//! Ξ-coalescent process.
+//!
+//! Coalescent processes with simultaneous multiple mergers, where several
+//! groups of sets of the partition can merge at the same time. They arise,
+//! for example, from diploid populations with high variance in the number of
+//! offspring: the lineages involved in a reproduction event are spread among
+//! the four parental chromosomes.
+//!
+//! Here the Ξ-coalescent is given by a measure Λ and a number of groups K.
+//! Events happen as in the Λ-coalescent: when there are b sets, any particular
+//! group of k of them takes part in an event at rate λ_{b,k}. Then, each
+//! participating set chooses uniformly one of the K groups, and the sets that
+//! chose the same group merge. With K = 4 and the Beta(2 - α, α) measure,
+//! this is the diploid Beta-Ξ-coalescent of Birkner et al. (2018).
+//!
+
+// Types
+use partitions::PartitionVec;
+use rand_distr::Exp;
+use rand::distributions::WeightedIndex;
+use crate::{Genealogy, Step};
+use crate::genealogy::apply_step;
+
+// Traits
+use crate::traits::LambdaMeasure;
+use markovian::traits::CMarkovChainTrait;
+use rand::distributions::Distribution;
+use rand::Rng;
+use core::fmt::Debug;
+use std::iter::FromIterator;
+
+/// Ξ-coalescent process in the space of partitions of the set {1, 2, ..., n}.
+/// Starts with a finite partition of all singletons and it ends with a single set.
+///
+/// It has a random number generator associated, R, to be a random iterator.
+///
+/// # Examples
+///
+/// The Dirac-Ξ-coalescent with four groups.
+/// ```
+/// use coalescence::{DiracMeasure, XiCoalescent};
+///
+/// let group_size = 100;
+/// let rng = rand::thread_rng();
+/// let coalescent = XiCoalescent::new(group_size, DiracMeasure::new(0.5), 4, rng);
+///
+/// let mut rng = rand::thread_rng();
+/// let genealogy = coalescent.sample_genealogy(&mut rng);
+/// let length = genealogy.length();
+/// ```
+#[derive(Debug, Clone)]
+pub struct XiCoalescent<L, R>
+where
+    L: LambdaMeasure,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    state: PartitionVec<()>,
+    measure: L,
+    groups: usize,
+    time: f64,
+    rng: R,
+}
+
+impl<L, R> XiCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Creates a new XiCoalescent, where sets taking part in an event are
+    /// split among ``groups`` groups.
+    ///
+    /// # Panics
+    ///
+    /// If ``groups`` is zero.
+    pub fn new(group_size: usize, measure: L, groups: usize, rng: R) -> Self {
+        assert!(groups > 0, "There must be at least one group.");
+        let state: PartitionVec<()> =
+            PartitionVec::from_iter((0..group_size).map(|_| ()));
+
+        XiCoalescent { state, measure, groups, time: 0.0, rng }
+    }
+
+    /// Creates a new XiCoalescent of a diploid population, where sets taking
+    /// part in an event are split among the four parental chromosomes.
+    ///
+    /// # Examples
+    ///
+    /// The diploid Beta-Ξ-coalescent.
+    /// ```
+    /// use coalescence::{BetaMeasure, XiCoalescent};
+    ///
+    /// let rng = rand::thread_rng();
+    /// let coalescent = XiCoalescent::diploid(100, BetaMeasure::new(1.2), rng);
+    ///
+    /// assert_eq!(coalescent.groups(), 4);
+    /// ```
+    pub fn diploid(group_size: usize, measure: L, rng: R) -> Self {
+        XiCoalescent::new(group_size, measure, 4, rng)
+    }
+
+    /// Current time of the process.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Measure Λ giving the rate of events.
+    pub fn measure(&self) -> &L {
+        &self.measure
+    }
+
+    /// Number of groups in which participating sets are split.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Change the internal random number generator for another.
+    pub fn set_rng(&mut self, other_rng: R) -> &mut Self {
+        self.rng = other_rng;
+        self
+    }
+
+    /// Peeks a possible next step, i.e. simultaneous mergers of sets, chosen
+    /// according to the stochastic process. This does not change the state of
+    /// the ``XiCoalescent``. Events that do not merge any sets are skipped.
+    ///
+    /// # Panics
+    ///
+    /// If no event can merge sets while there are several sets.
+    pub fn peek_next_step(&mut self) -> Option<(f64, Step)> {
+        let current_partition_size = self.state.amount_of_sets();
+
+        if current_partition_size == 1 {
+            None
+        } else {
+            // Simulate time step
+
+            let rates: Vec<f64> = self.measure
+                .merger_rates(current_partition_size)
+                .into_iter()
+                .enumerate()
+                .map(|(i, rate)| rate * merger_probability(i + 2, self.groups))
+                .collect();
+            let total_rate: f64 = rates.iter().sum();
+            assert!(total_rate > 0.0, "The merger rates must not be all zero.");
+            let time_step = Exp::new(total_rate).unwrap().sample(&mut self.rng);
+
+            // Choose between possible transitions
+
+            let participating = WeightedIndex::new(&rates).unwrap().sample(&mut self.rng) + 2;
+            let set_indexes = rand::seq::index::sample(&mut self.rng, current_partition_size, participating);
+
+            // Split in groups until some group has several sets
+
+            let mut chosen_groups: Vec<Vec<usize>>;
+            loop {
+                chosen_groups = vec![Vec::new(); self.groups];
+                for set_index in set_indexes.iter() {
+                    chosen_groups[self.rng.gen_range(0, self.groups)].push(set_index);
+                }
+                if chosen_groups.iter().any(|group| group.len() > 1) {
+                    break;
+                }
+            }
+
+            // Get values from these sets
+
+            let representatives: Vec<usize> = self.state
+                .all_sets()
+                .map(|mut set| set.next().unwrap().0)
+                .collect();
+            let step: Step = chosen_groups
+                .into_iter()
+                .filter(|group| group.len() > 1)
+                .map(|group| group.into_iter().map(|set_index| representatives[set_index]).collect())
+                .collect();
+
+            // Return
+
+            Some((time_step, step))
+        }
+    }
+
+    /// Changes to a next state of the ``XiCoalescent``, chosen
+    /// according to the stochastic process and returning the indexes of
+    /// elements that represent the sets of the partitions that were joint
+    /// to produce this next state.
+    pub fn next_step(&mut self) -> Option<(f64, Step)> {
+        match self.peek_next_step() {
+            Some((time_step, step)) => {
+                apply_step(&mut self.state, &step);
+                self.time += time_step;
+                Some((time_step, step))
+            },
+            None => None,
+        }
+    }
+
+    /// Sample a genealogy: from the initial partition of singletons until there
+    /// is only one set in the partition. Returns a ``Genealogy`` where
+    /// postprocess is possible.
+    ///
+    /// # Remarks
+    ///
+    /// No internal state changes, including the internal
+    /// random number generator. This is why this methods requires a rng.
+    ///
+    pub fn sample_genealogy<S>(&self, rng: &mut S) -> Genealogy
+    where
+        S: Rng + Clone + Debug,
+    {
+        // Initialize a XiCoalescent
+
+        let group_size: usize = self.state.len();
+        let mut coalescent_process = XiCoalescent::new(group_size, self.measure.clone(), self.groups, rng.clone());
+
+        // Generate transitions
+
+        let mut timed_steps = Vec::new();
+        while let Some((_, step)) = coalescent_process.next_step() {
+            timed_steps.push((coalescent_process.time(), step));
+        }
+
+        // Update rng
+
+        *rng = coalescent_process.rng.clone();
+
+        // Finish
+
+        Genealogy::from_timed_steps(group_size, timed_steps)
+    }
+}
+
+/// Probability that, among ``participating`` sets choosing uniformly one of
+/// ``groups`` groups, at least two choose the same group.
+fn merger_probability(participating: usize, groups: usize) -> f64 {
+    let all_different: f64 = (0..participating)
+        .map(|i| groups.saturating_sub(i) as f64 / groups as f64)
+        .product();
+    1.0 - all_different
+}
+
+impl<L, R> CMarkovChainTrait<PartitionVec<()>> for XiCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    /// Current state of the process.
+    fn state(&self) -> &PartitionVec<()> {
+        &self.state
+    }
+
+    /// Change the current state of the process.
+    fn set_state(&mut self, state: PartitionVec<()>) -> &mut Self {
+        self.state = state;
+        self
+    }
+}
+
+impl<L, R> Iterator for XiCoalescent<L, R>
+where
+    L: LambdaMeasure + Clone,
+    R: Rng + Clone + core::fmt::Debug,
+{
+    type Item = (f64, PartitionVec<()>);
+
+    /// Changes the state of the ``XiCoalescent`` to a new state, chosen
+    /// according to the stochastic process.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_step() {
+            Some((time_step, _)) => Some((time_step, self.state.clone())),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BetaMeasure, DiracMeasure};
+    use petgraph::Graph;
+    use rand::SeedableRng;
+
+    #[test]
+    fn probability_of_merger() {
+        assert_eq!(merger_probability(2, 1), 1.0);
+        assert_eq!(merger_probability(2, 4), 0.25);
+        assert_eq!(merger_probability(5, 4), 1.0);
+    }
+
+    #[test]
+    fn simultaneous_mergers_statistics() {
+        let group_size = 30;
+        let rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let coalescent = XiCoalescent::new(group_size, DiracMeasure::new(0.8), 4, rng);
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(9));
+
+        assert!(genealogy.steps().iter().any(|step| step.len() > 1));
+
+        let pairs = group_size * (group_size - 1) / 2;
+        let mean_divergence = (0..group_size)
+            .flat_map(|i| ((i + 1)..group_size).map(move |j| (i, j)))
+            .map(|(i, j)| genealogy.divergence(i, j))
+            .sum::<f64>() / pairs as f64;
+        assert!((genealogy.mean_pairwise_divergence() - mean_divergence).abs() < 1e-10);
+
+        let length = genealogy.length();
+        let graph: Graph<(usize, usize), f64, petgraph::Undirected, u32> = genealogy.into();
+        let graph_length: f64 = graph.raw_edges().iter().map(|edge| edge.weight).sum();
+        assert!((length - graph_length).abs() < 1e-10);
+    }
+
+    #[test]
+    fn diploid_beta() {
+        let rng = rand_pcg::Pcg32::seed_from_u64(0);
+        let coalescent = XiCoalescent::diploid(20, BetaMeasure::new(1.5), rng);
+        let genealogy = coalescent.sample_genealogy(&mut rand_pcg::Pcg32::seed_from_u64(10));
+
+        assert_eq!(genealogy.path().last().unwrap().amount_of_sets(), 1);
+    }
+}